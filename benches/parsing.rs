@@ -0,0 +1,60 @@
+//! Benchmarks for the lexer and parser.
+//!
+//! Run them with `cargo bench`.
+
+#[macro_use]
+extern crate criterion;
+extern crate lishp;
+
+use criterion::{black_box, Criterion};
+use lishp::{tokenize, Parser};
+
+/// A big chunk of source code made up of lots of small top-level forms.
+fn broad_source() -> String {
+    let mut src = String::new();
+
+    for i in 0..1000 {
+        src.push_str(&format!("; function number {}\n", i));
+        src.push_str(&format!("(define (func-{} x y)\n  (+ x (* y {}) \"a string\" 3.14))\n",
+                              i,
+                              i));
+    }
+
+    src
+}
+
+/// A single form which is nested very deeply.
+fn deep_source() -> String {
+    let depth = 500;
+    let mut src = String::new();
+
+    for i in 0..depth {
+        src.push_str(&format!("(+ {} ", i));
+    }
+    for _ in 0..depth {
+        src.push(')');
+    }
+
+    src
+}
+
+fn bench_tokenize(c: &mut Criterion) {
+    let src = broad_source();
+
+    c.bench_function("tokenize a large file", |b| b.iter(|| tokenize(black_box(src.as_str()))));
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let broad = tokenize(broad_source()).unwrap();
+    let deep = tokenize(deep_source()).unwrap();
+
+    c.bench_function("parse many top-level forms", |b| {
+        b.iter(|| Parser::new(black_box(broad.clone())).parse_program())
+    });
+    c.bench_function("parse a deeply nested form", |b| {
+        b.iter(|| Parser::new(black_box(deep.clone())).parse_program())
+    });
+}
+
+criterion_group!(benches, bench_tokenize, bench_parse);
+criterion_main!(benches);
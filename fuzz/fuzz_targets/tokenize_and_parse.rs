@@ -0,0 +1,36 @@
+//! Throw arbitrary bytes at the lexer and parser. Neither of them should ever
+//! panic, no matter how badly formed the input is.
+//!
+//! Run with `cargo fuzz run tokenize_and_parse`. If it turns anything up, add
+//! the offending input as a regression test next to the code that broke.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate lishp;
+
+use std::str;
+
+use lishp::Parser;
+
+fuzz_target!(|data: &[u8]| {
+    let src = match str::from_utf8(data) {
+        Ok(src) => src,
+        Err(_) => return,
+    };
+
+    let tokens = match lishp::tokenize_all(src) {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            let _ = lishp::errors::render_error("fuzz.lisp", src, e.pos(), "invalid token");
+            return;
+        }
+    };
+
+    if let Ok(forms) = Parser::new(tokens).with_comments(true).parse_program() {
+        for form in forms {
+            let _ = form.ast.to_string();
+            let _ = form.ast.to_sexpr_string();
+        }
+    }
+});
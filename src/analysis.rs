@@ -0,0 +1,768 @@
+//! Static analysis passes which look for suspicious code without actually
+//! running it.
+//!
+//! None of these passes change the AST, they just walk it and report back
+//! anything which looks like it's probably a mistake.
+
+use std::collections::HashSet;
+
+use errors::{LishpError, LishpResult};
+use lexer::Span;
+use parser::{SpanTree, Spanned};
+use types::Type;
+
+
+/// Something suspicious found while analysing a program.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    /// A human readable description of the problem.
+    pub message: String,
+
+    /// Where the problem is (e.g. the `let` binding which is never used), or
+    /// a synthetic span if the AST didn't come with spans.
+    pub span: Span,
+}
+
+impl Warning {
+    fn new<T: Into<String>>(message: T, span: Span) -> Warning {
+        Warning {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+/// Look through an AST for code which is almost certainly a mistake, such as
+/// a `cond` clause which can never be reached or trying to call a number.
+///
+/// It also points out `let` bindings which are never used or which shadow a
+/// variable from an outer scope. Prefix a binding's name with an underscore
+/// (e.g. `_unused`) if it's meant to be unused.
+///
+/// A plain `Type` doesn't know where it came from, so every warning gets a
+/// synthetic span. Use `analyze_spanned()` if you want to know where each
+/// problem is.
+///
+/// # Examples
+///
+/// ```
+/// # use lishp::{tokenize, parse};
+/// use lishp::analysis::analyze;
+///
+/// let src = "(cond (else 1) ((= x 2) 3))";
+/// let ast = parse(tokenize(src).unwrap()).unwrap();
+///
+/// let warnings = analyze(&ast);
+/// assert_eq!(warnings.len(), 1);
+/// ```
+pub fn analyze(ast: &Type) -> Vec<Warning> {
+    let mut warnings = vec![];
+    check(ast, Span::synthetic(), &[], &mut vec![], &mut warnings);
+    warnings
+}
+
+/// The same as `analyze()`, except each warning points at the code which
+/// triggered it.
+///
+/// # Examples
+///
+/// ```
+/// # use lishp::lexer::{tokenize, Span};
+/// # use lishp::parser::Parser;
+/// use lishp::analysis::analyze_spanned;
+///
+/// let tokens = tokenize("(print (5 x))").unwrap();
+/// let ast = Parser::new(tokens).parse_spanned().unwrap();
+///
+/// let warnings = analyze_spanned(&ast);
+/// assert_eq!(warnings[0].span, Span::new(7, 12));
+/// ```
+pub fn analyze_spanned(ast: &Spanned<Type>) -> Vec<Warning> {
+    let mut warnings = vec![];
+    check(&ast.node, ast.span, &ast.children, &mut vec![], &mut warnings);
+    warnings
+}
+
+/// Check a node, where `span` and `children` say where it and its children
+/// are, and `scope` contains every variable currently in scope.
+fn check(node: &Type,
+         span: Span,
+         children: &[SpanTree],
+         scope: &mut Vec<String>,
+         warnings: &mut Vec<Warning>) {
+    let (head, tail) = match node.match_list() {
+        Some(split) => split,
+        None => return,
+    };
+    let tail_spans = children.get(1..).unwrap_or(&[]);
+
+    match *head {
+        // quoted code is just data, so anything goes
+        Type::Symbol(ref s) if s == "quote" => return,
+        Type::Symbol(ref s) if s == "cond" => {
+            check_cond(tail, tail_spans, scope, warnings);
+            return;
+        }
+        Type::Symbol(ref s) if s == "let" => {
+            check_let(tail, tail_spans, scope, warnings);
+            return;
+        }
+        Type::Symbol(ref s) if s == "lambda" || s == "define" => {
+            if let Some((Type::List(signature), body)) = tail.split_first() {
+                // `(define (f x) ...)` binds `f` outside the function's body
+                let skip = if s == "define" { 1 } else { 0 };
+                let params = signature.iter().skip(skip).filter_map(symbol_name);
+                let body_spans = tail_spans.get(1..).unwrap_or(&[]);
+                check_in_scope(body, body_spans, params.collect(), scope, warnings);
+                return;
+            }
+        }
+        Type::Integer(_) | Type::Float(_) | Type::String(_) | Type::Boolean(_) | Type::Nil => {
+            let message = format!("Calling a non-function literal, `{}`", head);
+            warnings.push(Warning::new(message, span));
+        }
+        _ => {}
+    }
+
+    for (i, child) in node.children().enumerate() {
+        let (span, children) = child_span(children, i);
+        check(child, span, children, scope, warnings);
+    }
+}
+
+fn check_cond(clauses: &[Type],
+              spans: &[SpanTree],
+              scope: &mut Vec<String>,
+              warnings: &mut Vec<Warning>) {
+    let else_position = clauses.iter().position(|clause| match *clause {
+        Type::List(ref items) => items.first() == Some(&Type::Symbol("else".to_string())),
+        _ => false,
+    });
+
+    if let Some(i) = else_position {
+        if i + 1 < clauses.len() {
+            // point at the first clause which can't be reached
+            let (span, _) = child_span(spans, i + 1);
+            warnings.push(Warning::new("Any `cond` clauses after `else` are unreachable", span));
+        }
+    }
+
+    // A clause isn't a function call, but everything inside it is code
+    for (i, clause) in clauses.iter().enumerate() {
+        let (_, clause_spans) = child_span(spans, i);
+        for (j, child) in clause.children().enumerate() {
+            let (span, children) = child_span(clause_spans, j);
+            check(child, span, children, scope, warnings);
+        }
+    }
+}
+
+fn check_let(tail: &[Type],
+             spans: &[SpanTree],
+             scope: &mut Vec<String>,
+             warnings: &mut Vec<Warning>) {
+    let (bindings, body) = match tail.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let (_, binding_spans) = child_span(spans, 0);
+
+    let mut names = vec![];
+    for (i, binding) in bindings.children().enumerate() {
+        let (binding_span, part_spans) = child_span(binding_spans, i);
+        let mut parts = binding.children();
+        if let Some(name) = parts.next().and_then(symbol_name) {
+            names.push((name, binding_span));
+        }
+
+        // the initial values are evaluated in the outer scope
+        for (j, value) in parts.enumerate() {
+            let (span, children) = child_span(part_spans, j + 1);
+            check(value, span, children, scope, warnings);
+        }
+    }
+
+    let used: HashSet<String> = body.iter().flat_map(free_variables).collect();
+
+    for &(ref name, span) in &names {
+        if scope.contains(name) {
+            let message = format!("The binding `{}` shadows an outer variable", name);
+            warnings.push(Warning::new(message, span));
+        }
+        if !name.starts_with('_') && !used.contains(name) {
+            let message = format!("The binding `{}` is never used", name);
+            warnings.push(Warning::new(message, span));
+        }
+    }
+
+    let names = names.into_iter().map(|(name, _)| name).collect();
+    check_in_scope(body, spans.get(1..).unwrap_or(&[]), names, scope, warnings);
+}
+
+/// Check a block of code with some extra variables in scope.
+fn check_in_scope(body: &[Type],
+                  spans: &[SpanTree],
+                  names: Vec<String>,
+                  scope: &mut Vec<String>,
+                  warnings: &mut Vec<Warning>) {
+    let outer_len = scope.len();
+    scope.extend(names);
+
+    for (i, expr) in body.iter().enumerate() {
+        let (span, children) = child_span(spans, i);
+        check(expr, span, children, scope, warnings);
+    }
+
+    scope.truncate(outer_len);
+}
+
+fn symbol_name(node: &Type) -> Option<String> {
+    match *node {
+        Type::Symbol(ref name) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+/// Make sure every special form (`if`, `define` and `lambda`) in a program
+/// has the right shape, returning an error for the first one which doesn't.
+///
+/// # Examples
+///
+/// ```
+/// # use lishp::{tokenize, parse, LishpError};
+/// # use lishp::lexer::Span;
+/// use lishp::analysis::validate;
+///
+/// let ast = parse(tokenize("(print (if))").unwrap()).unwrap();
+///
+/// let message = "`if` requires a condition and a then-branch";
+/// assert_eq!(validate(&ast), Err(LishpError::MalformedForm(message, Span::synthetic())));
+/// ```
+pub fn validate(ast: &Type) -> LishpResult<()> {
+    validate_node(ast, Span::synthetic(), &[])
+}
+
+/// The same as `validate()`, except the error says where the malformed form
+/// is.
+///
+/// # Examples
+///
+/// ```
+/// # use lishp::lexer::{tokenize, Span};
+/// # use lishp::parser::Parser;
+/// # use lishp::LishpError;
+/// use lishp::analysis::validate_spanned;
+///
+/// let ast = Parser::new(tokenize("(print (if))").unwrap()).parse_spanned().unwrap();
+///
+/// let message = "`if` requires a condition and a then-branch";
+/// assert_eq!(validate_spanned(&ast), Err(LishpError::MalformedForm(message, Span::new(7, 11))));
+/// ```
+pub fn validate_spanned(ast: &Spanned<Type>) -> LishpResult<()> {
+    validate_node(&ast.node, ast.span, &ast.children)
+}
+
+fn validate_node(ast: &Type, span: Span, children: &[SpanTree]) -> LishpResult<()> {
+    let (head, tail) = match ast.match_list() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+
+    match *head {
+        Type::Symbol(ref s) if s == "quote" => return Ok(()),
+        Type::Symbol(ref s) => {
+            validate_special_form(s, tail)
+                .map_err(|message| LishpError::MalformedForm(message, span))?
+        }
+        _ => {}
+    }
+
+    for (i, child) in ast.children().enumerate() {
+        let (span, children) = child_span(children, i);
+        validate_node(child, span, children)?;
+    }
+
+    Ok(())
+}
+
+/// Find where the `i`th child of a node is, falling back to a synthetic span
+/// when we don't know.
+fn child_span(children: &[SpanTree], i: usize) -> (Span, &[SpanTree]) {
+    match children.get(i) {
+        Some(tree) => (tree.span, &tree.children),
+        None => (Span::synthetic(), &[]),
+    }
+}
+
+fn validate_special_form(name: &str, tail: &[Type]) -> Result<(), &'static str> {
+    let all_symbols = |items: &[Type]| items.iter().all(|item| symbol_name(item).is_some());
+
+    match name {
+        "if" if tail.len() < 2 => Err("`if` requires a condition and a then-branch"),
+        "if" if tail.len() > 3 => Err("`if` can't have more than one else-branch"),
+        "define" => {
+            match tail.split_first() {
+                Some((Type::Symbol(_), value)) => {
+                    match value.len() {
+                        0 => Err("`define` requires a name and a value"),
+                        1 => Ok(()),
+                        _ => Err("`define` can only give a variable one value"),
+                    }
+                }
+                Some((Type::List(signature), body)) => {
+                    if !all_symbols(signature) {
+                        Err("`define` requires the function name and parameters to be symbols")
+                    } else if body.is_empty() {
+                        Err("`define` requires a function body")
+                    } else {
+                        Ok(())
+                    }
+                }
+                Some(_) => Err("`define` requires a symbol or a function signature as its name"),
+                None => Err("`define` requires a name and a value"),
+            }
+        }
+        "lambda" => {
+            match tail.split_first() {
+                Some((params, body)) if !body.is_empty() => {
+                    match *params {
+                        Type::Symbol(_) | Type::Nil => Ok(()),
+                        Type::List(ref params) if all_symbols(params) => Ok(()),
+                        _ => Err("`lambda` parameters must be symbols"),
+                    }
+                }
+                _ => Err("`lambda` requires a parameter list and a body"),
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Special forms which don't bind any variables. Their names are keywords
+/// rather than variables, so they aren't free.
+const SPECIAL_FORMS: &[&str] = &["if", "begin", "and", "or", "when", "unless", "set!"];
+
+/// Get every variable an expression refers to which isn't bound by the
+/// expression itself (i.e. the variables it would need to capture if it were
+/// a closure).
+///
+/// Variables bound with `let`, `lambda` or `define` aren't free inside the body
+/// of that form, quoted data is ignored, and special forms like `if` aren't
+/// variables at all. A `define` inside a body binds its name for the whole
+/// body.
+///
+/// # Examples
+///
+/// ```
+/// # use lishp::{tokenize, parse};
+/// use lishp::analysis::free_variables;
+///
+/// let ast = parse(tokenize("(lambda (x) (* x factor))").unwrap()).unwrap();
+/// let free = free_variables(&ast);
+///
+/// assert!(free.contains("factor"));
+/// assert!(!free.contains("x"));
+/// ```
+pub fn free_variables(expr: &Type) -> HashSet<String> {
+    let mut free = HashSet::new();
+    collect_free(expr, &mut vec![], &mut free);
+    free
+}
+
+/// Find the free variables in a node, where `bound` contains every variable
+/// bound by an enclosing form.
+fn collect_free(node: &Type, bound: &mut Vec<String>, free: &mut HashSet<String>) {
+    let items = match *node {
+        Type::Symbol(ref name) => {
+            if !bound.contains(name) {
+                let _ = free.insert(name.clone());
+            }
+            return;
+        }
+        Type::List(ref items) if !items.is_empty() => items,
+        _ => return,
+    };
+    let tail = &items[1..];
+
+    let name = match items[0] {
+        Type::Symbol(ref name) => name.as_str(),
+        _ => "",
+    };
+
+    match name {
+        "quote" => {}
+        "lambda" => {
+            if let Some((params, body)) = tail.split_first() {
+                let names = match *params {
+                    Type::Symbol(ref rest) => vec![rest.clone()],
+                    _ => params.children().filter_map(symbol_name).collect(),
+                };
+                collect_free_in_scope(body, names, bound, free);
+            }
+        }
+        "define" => {
+            match tail.split_first() {
+                // the function's own name is in scope so it can recurse
+                Some((Type::List(signature), body)) => {
+                    let names = signature.iter().filter_map(symbol_name).collect();
+                    collect_free_in_scope(body, names, bound, free);
+                }
+                Some((_, value)) => {
+                    for expr in value {
+                        collect_free(expr, bound, free);
+                    }
+                }
+                None => {}
+            }
+        }
+        "let" => {
+            if let Some((bindings, body)) = tail.split_first() {
+                let mut names = vec![];
+                for binding in bindings.children() {
+                    let mut parts = binding.children();
+                    names.extend(parts.next().and_then(symbol_name));
+                    for value in parts {
+                        collect_free(value, bound, free);
+                    }
+                }
+                collect_free_in_scope(body, names, bound, free);
+            }
+        }
+        "cond" => {
+            for part in tail.iter().flat_map(Type::children) {
+                if *part != Type::Symbol("else".to_string()) {
+                    collect_free(part, bound, free);
+                }
+            }
+        }
+        name if SPECIAL_FORMS.contains(&name) => {
+            for expr in tail {
+                collect_free(expr, bound, free);
+            }
+        }
+        _ => {
+            for item in items {
+                collect_free(item, bound, free);
+            }
+        }
+    }
+}
+
+fn collect_free_in_scope(body: &[Type],
+                         names: Vec<String>,
+                         bound: &mut Vec<String>,
+                         free: &mut HashSet<String>) {
+    let outer_len = bound.len();
+    bound.extend(names);
+    bound.extend(body.iter().filter_map(defined_name));
+
+    for expr in body {
+        collect_free(expr, bound, free);
+    }
+
+    bound.truncate(outer_len);
+}
+
+/// If this is a `define`, get the name it defines.
+fn defined_name(node: &Type) -> Option<String> {
+    let mut parts = node.children();
+    if parts.next() != Some(&Type::Symbol("define".to_string())) {
+        return None;
+    }
+
+    match parts.next() {
+        Some(Type::List(signature)) => signature.first().and_then(symbol_name),
+        Some(other) => symbol_name(other),
+        None => None,
+    }
+}
+
+/// Find every expression in a function body which is in tail position (i.e.
+/// its value is returned straight from the function without any more work
+/// being done).
+///
+/// The `body` itself is always in tail position. From there, the branches of
+/// an `if` or `cond` and the last expression of a `begin`, `let`, `and`,
+/// `or`, `when` or `unless` inherit tail position from their parent. Nested
+/// `lambda`s are left alone because their bodies belong to a different
+/// function.
+///
+/// Expressions are identified by their address, so the returned pointers are
+/// only meaningful for as long as `body` isn't moved or mutated.
+///
+/// # Examples
+///
+/// ```
+/// # use lishp::{tokenize, parse};
+/// use lishp::analysis::tail_positions;
+///
+/// let body = parse(tokenize("(if x (f 1) 2)").unwrap()).unwrap();
+/// let tails = tail_positions(&body);
+///
+/// // the `if` itself, `(f 1)` and `2`
+/// assert_eq!(tails.len(), 3);
+/// ```
+pub fn tail_positions(body: &Type) -> HashSet<*const Type> {
+    let mut tails = HashSet::new();
+    mark_tail(body, &mut tails);
+    tails
+}
+
+fn mark_tail(node: &Type, tails: &mut HashSet<*const Type>) {
+    let _ = tails.insert(node);
+
+    let (head, tail) = match node.match_list() {
+        Some(split) => split,
+        None => return,
+    };
+    let name = match *head {
+        Type::Symbol(ref s) => s.as_str(),
+        _ => return,
+    };
+
+    match name {
+        "if" => {
+            for branch in tail.iter().skip(1) {
+                mark_tail(branch, tails);
+            }
+        }
+        "cond" => {
+            for clause in tail {
+                if let Some(last) = clause.children().last() {
+                    mark_tail(last, tails);
+                }
+            }
+        }
+        "begin" | "and" | "or" => {
+            if let Some(last) = tail.last() {
+                mark_tail(last, tails);
+            }
+        }
+        "let" | "when" | "unless" => {
+            if let Some(last) = tail.iter().skip(1).last() {
+                mark_tail(last, tails);
+            }
+        }
+        _ => {}
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::tokenize;
+    use parser::{parse, Parser};
+
+    fn ptr(node: &Type) -> *const Type {
+        node
+    }
+
+    fn unspanned(message: &str) -> Warning {
+        Warning::new(message, Span::synthetic())
+    }
+
+    fn analyze_src(src: &str) -> Vec<Warning> {
+        let ast = parse(tokenize(src).unwrap()).unwrap();
+        analyze(&ast)
+    }
+
+    #[test]
+    fn clean_code_has_no_warnings() {
+        let inputs = vec!["(+ 1 2)",
+                          "(cond ((= x 1) 2) (true 3) (else 4))",
+                          "(define (f x) (if x (f 1) (quote (1 2 3))))",
+                          "(let ((x 1) (_y 2)) (let ((z x)) (+ x z)))",
+                          "(lambda (x) (let ((y (* x 2))) y))",
+                          "5"];
+
+        for src in inputs {
+            let got = analyze_src(src);
+            assert_eq!(got, vec![], "{}", src);
+        }
+    }
+
+    #[test]
+    fn else_must_be_the_last_cond_clause() {
+        let got = analyze_src("(cond ((= x 1) 2) (else 3) ((= x 4) 5))");
+        let should_be = vec![unspanned("Any `cond` clauses after `else` are unreachable")];
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn warnings_point_at_the_code_which_triggered_them() {
+        let inputs = vec![("\n(cond (else 1) (x 2) (y 3))", Span::new(16, 21)),
+                          ("(define (f x)\n  (print (5 x)))", Span::new(23, 28)),
+                          ("(cond (x (\"foo\")))", Span::new(9, 16))];
+
+        for (src, span) in inputs {
+            let ast = Parser::new(tokenize(src).unwrap()).parse_spanned().unwrap();
+
+            let got = analyze_spanned(&ast);
+            assert_eq!(got.len(), 1, "{}", src);
+            assert_eq!(got[0].span, span, "{}", src);
+
+            assert!(analyze(&ast.node)[0].span.is_synthetic());
+        }
+    }
+
+    #[test]
+    fn calling_a_literal() {
+        let got = analyze_src("(print (5 x) (cond (x (\"foo\"))))");
+        let should_be = vec![unspanned("Calling a non-function literal, `5`"),
+                             unspanned("Calling a non-function literal, `foo`")];
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn unused_let_bindings() {
+        let got = analyze_src("(let ((x 1) (y 2)) (print x (quote y)))");
+        let should_be = vec![unspanned("The binding `y` is never used")];
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn let_bindings_which_shadow_outer_variables() {
+        let got = analyze_src("(define (f x) (let ((x 1) (y x)) (let ((y 2)) (+ x y))))");
+        // the outer `y` is never used because the inner one hides it
+        let should_be = vec![unspanned("The binding `x` shadows an outer variable"),
+                             unspanned("The binding `y` is never used"),
+                             unspanned("The binding `y` shadows an outer variable")];
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn let_binding_warnings_point_at_the_binding() {
+        let inputs = vec![("(define (f x)\n  (let ((y 1)\n        (z 2))\n    y))",
+                           vec![Warning::new("The binding `z` is never used", Span::new(36, 41))]),
+                          ("(let ((x 1)) (let ((x 2)) x))",
+                           vec![Warning::new("The binding `x` is never used", Span::new(6, 11)),
+                                Warning::new("The binding `x` shadows an outer variable",
+                                             Span::new(19, 24))])];
+
+        for (src, should_be) in inputs {
+            let ast = Parser::new(tokenize(src).unwrap()).parse_spanned().unwrap();
+            assert_eq!(analyze_spanned(&ast), should_be, "{}", src);
+        }
+    }
+
+    fn free_in(src: &str) -> Vec<String> {
+        let ast = parse(tokenize(src).unwrap()).unwrap();
+        let mut free: Vec<String> = free_variables(&ast).into_iter().collect();
+        free.sort();
+        free
+    }
+
+    #[test]
+    fn free_variables_of_binding_forms() {
+        let inputs = vec![("(lambda (x) (+ x y))", vec!["+", "y"]),
+                          ("(lambda args (f args))", vec!["f"]),
+                          ("(let ((a b)) (f a))", vec!["b", "f"]),
+                          ("(let ((a 1)) (let ((a a)) a))", vec![]),
+                          ("(define (f n) (if n (f (- n 1)) m))", vec!["-", "m"]),
+                          ("(define x (+ y 1))", vec!["+", "y"]),
+                          ("(lambda () (define y 1) y)", vec![])];
+
+        for (src, should_be) in inputs {
+            assert_eq!(free_in(src), should_be, "{}", src);
+        }
+    }
+
+    #[test]
+    fn quoted_data_and_keywords_are_not_free() {
+        let got = free_in("(if (= x 1) (quote (z)) (cond ((zero? x) w) (else v)))");
+        assert_eq!(got, vec!["=", "v", "w", "x", "zero?"]);
+    }
+
+    fn validate_src(src: &str) -> LishpResult<()> {
+        let ast = parse(tokenize(src).unwrap()).unwrap();
+        validate(&ast)
+    }
+
+    #[test]
+    fn well_formed_special_forms_are_valid() {
+        let inputs = vec!["(if x 1)",
+                          "(if x 1 2)",
+                          "(define x 1)",
+                          "(define (f x y) (+ x y))",
+                          "(lambda () 1)",
+                          "(lambda args args)",
+                          "(lambda (x) (if x (quote (if)) 2))"];
+
+        for src in inputs {
+            assert_eq!(validate_src(src), Ok(()), "{}", src);
+        }
+    }
+
+    #[test]
+    fn malformed_special_forms_are_errors() {
+        let inputs = vec![("(if)", "`if` requires a condition and a then-branch"),
+                          ("(print (if x))", "`if` requires a condition and a then-branch"),
+                          ("(if x 1 2 3)", "`if` can't have more than one else-branch"),
+                          ("(define)", "`define` requires a name and a value"),
+                          ("(define x)", "`define` requires a name and a value"),
+                          ("(define x 1 2)", "`define` can only give a variable one value"),
+                          ("(define (f x))", "`define` requires a function body"),
+                          ("(define (f 1) 2)",
+                           "`define` requires the function name and parameters to be symbols"),
+                          ("(define 5 1)",
+                           "`define` requires a symbol or a function signature as its name"),
+                          ("(lambda)", "`lambda` requires a parameter list and a body"),
+                          ("(lambda (x))", "`lambda` requires a parameter list and a body"),
+                          ("(lambda (x \"y\") x)", "`lambda` parameters must be symbols")];
+
+        for (src, message) in inputs {
+            let should_be = LishpError::MalformedForm(message, Span::synthetic());
+            assert_eq!(validate_src(src), Err(should_be), "{}", src);
+        }
+    }
+
+    #[test]
+    fn malformed_forms_point_at_the_offending_form() {
+        let src = "(define (f x)\n  (print\n    (if x)))";
+        let ast = Parser::new(tokenize(src).unwrap()).parse_spanned().unwrap();
+
+        let message = "`if` requires a condition and a then-branch";
+        let should_be = LishpError::MalformedForm(message, Span::new(27, 33));
+        assert_eq!(validate_spanned(&ast), Err(should_be));
+    }
+
+    #[test]
+    fn tail_positions_of_an_if_in_a_lambda_body() {
+        let lambda = parse(tokenize("(lambda (n) (if (= n 0) 1 (f (- n 1))))").unwrap()).unwrap();
+        let body = &lambda.children().as_slice()[2];
+        let if_parts = body.children().as_slice();
+
+        let got = tail_positions(body);
+
+        assert_eq!(got.len(), 3);
+        assert!(got.contains(&ptr(body)));
+        assert!(got.contains(&ptr(&if_parts[2])));
+        assert!(got.contains(&ptr(&if_parts[3])));
+        assert!(!got.contains(&ptr(&if_parts[1])));
+    }
+
+    #[test]
+    fn only_the_last_expression_of_a_block_is_a_tail() {
+        let body = parse(tokenize("(let ((x 1)) (print x) (begin (g x) (f x)))").unwrap()).unwrap();
+        let block = &body.children().as_slice()[3];
+        let last = &block.children().as_slice()[2];
+
+        let got = tail_positions(&body);
+
+        let should_be: HashSet<_> = vec![ptr(&body), ptr(block), ptr(last)].into_iter().collect();
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn nested_lambda_bodies_are_not_tails() {
+        let body = parse(tokenize("(lambda (x) (f x))").unwrap()).unwrap();
+
+        let got = tail_positions(&body);
+        assert_eq!(got.len(), 1);
+    }
+}
@@ -3,9 +3,14 @@ extern crate lishp;
 use std::env::args;
 use std::fs::File;
 use std::io::Read;
-use std::path::PathBuf;
 use std::process::exit;
 
+use lishp::analysis::validate_spanned;
+use lishp::errors::render_error;
+use lishp::lexer::{InvalidTokenKind, Span};
+use lishp::parser::Spanned;
+use lishp::{LishpError, Parser};
+
 const USAGE: &'static str = "interpreter <file>";
 
 fn main() {
@@ -14,27 +19,66 @@ fn main() {
         exit(1)
     });
 
-    let path = PathBuf::from(filename);
-
     let mut src = String::new();
-    let mut f = File::open(path).unwrap();
+    let mut f = File::open(&filename).unwrap();
     f.read_to_string(&mut src).unwrap();
 
-    let tokens = match lishp::tokenize(src) {
+    let tokens = match lishp::tokenize(src.as_str()) {
         Ok(tokens) => tokens,
         Err(e) => {
-            println!("Syntax Error: {:?}", e);
+            let message = match e.kind() {
+                InvalidTokenKind::Unrecognised => "Syntax Error: invalid token",
+                InvalidTokenKind::TooLong => "Syntax Error: token is too long",
+            };
+            eprint!("{}", render_error(&filename, &src, e.pos(), message));
             exit(1);
         }
     };
 
-    let ast = match lishp::parse(tokens) {
-        Ok(ast) => ast,
+    // Parse errors only know which token they happened at, so remember where
+    // each token is in the source code
+    let spans: Vec<Span> = tokens.iter().map(|tok| tok.span()).collect();
+
+    let forms = match Parser::new(tokens).parse_program() {
+        Ok(forms) => forms,
         Err(e) => {
-            println!("Parsing Error: {:?}", e);
+            let message = format!("Parsing Error: {}", e);
+            match token_index(&e).and_then(|i| spans.get(i)) {
+                Some(span) => eprint!("{}", render_error(&filename, &src, span.start(), &message)),
+                None => eprintln!("{}: {}", filename, message),
+            }
             exit(1);
         }
     };
 
-    // TODO: Run `eval` on the AST to start the actual interpreting.
+    for form in forms {
+        let form = Spanned::from(form);
+
+        if let Err(e) = validate_spanned(&form) {
+            let span = match e {
+                LishpError::MalformedForm(_, span) if !span.is_synthetic() => span,
+                _ => form.span,
+            };
+            let message = format!("Syntax Error: {}", e);
+            eprint!("{}", render_error(&filename, &src, span.start(), &message));
+            exit(1);
+        }
+    }
+
+    // TODO: Run `eval` on each form to start the actual interpreting.
+}
+
+/// Get the index of the token a `LishpError` refers to, if there is one. For
+/// unclosed parens that's the outermost one.
+fn token_index(e: &LishpError) -> Option<usize> {
+    match *e {
+        LishpError::EOF(ref open_parens, _) => open_parens.first().cloned(),
+        LishpError::UnexpectedToken(i, _, _) |
+        LishpError::UnbalancedParens(i) |
+        LishpError::NestingTooDeep(i) => Some(i),
+        LishpError::InvalidNumber(_) |
+        LishpError::InvalidToken(_) |
+        LishpError::MalformedForm(_, _) |
+        LishpError::WrongLength(_, _) => None,
+    }
 }
@@ -0,0 +1,223 @@
+//! A lossless Concrete Syntax Tree.
+//!
+//! Unlike the AST you get from the `Parser`, a CST keeps every single token
+//! from the original source code, including whitespace and comments. That
+//! makes it a good starting point for tools like formatters, where throwing
+//! away the programmer's layout isn't an option.
+
+use errors::{LishpError, LishpResult};
+use lexer::{Lexer, Span, Token};
+use parser::DEFAULT_MAX_DEPTH;
+
+
+/// A node in the Concrete Syntax Tree.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CstNode {
+    /// Everything in a source file, in order.
+    Root(Vec<CstNode>),
+
+    /// A list, along with its parens.
+    List {
+        /// The opening paren.
+        open: Token,
+        /// Everything between the parens, including trivia.
+        children: Vec<CstNode>,
+        /// The closing paren.
+        close: Token,
+    },
+
+    /// A number, string, symbol, or any other atom.
+    Atom(Token),
+
+    /// Whitespace or a comment.
+    Trivia(Token),
+}
+
+impl CstNode {
+    /// Turn the tree back into the source code it came from.
+    pub fn to_source(&self) -> String {
+        let mut buffer = String::new();
+        self.write_source(&mut buffer);
+        buffer
+    }
+
+    fn write_source(&self, buffer: &mut String) {
+        match *self {
+            CstNode::Root(ref children) => {
+                for child in children {
+                    child.write_source(buffer);
+                }
+            }
+            CstNode::List { ref open, ref children, ref close } => {
+                buffer.push_str(open.value());
+                for child in children {
+                    child.write_source(buffer);
+                }
+                buffer.push_str(close.value());
+            }
+            CstNode::Atom(ref tok) |
+            CstNode::Trivia(ref tok) => buffer.push_str(tok.value()),
+        }
+    }
+
+    /// Where this node is in the source code. A `Root` always covers the
+    /// entire source.
+    pub fn span(&self) -> Span {
+        match *self {
+            CstNode::Root(ref children) => {
+                let end = children.last().map_or(0, |child| child.span().end());
+                Span::new(0, end)
+            }
+            CstNode::List { ref open, ref close, .. } => {
+                Span::new(open.span().start(), close.span().end())
+            }
+            CstNode::Atom(ref tok) |
+            CstNode::Trivia(ref tok) => tok.span(),
+        }
+    }
+}
+
+
+/// Parse some source code into a Concrete Syntax Tree.
+///
+/// The positions in any errors are token indices, the same as you'd get
+/// from running `tokenize()` and `parse()`, so they can be looked up in the
+/// output of `tokenize()`. The exception is `LishpError::InvalidToken`,
+/// which happens before there are any tokens and contains a byte offset.
+///
+/// # Examples
+///
+/// ```
+/// use lishp::cst::parse_cst;
+///
+/// let src = "(print 1) ; a comment\n";
+/// let cst = parse_cst(src).unwrap();
+///
+/// assert_eq!(cst.to_source(), src);
+/// ```
+pub fn parse_cst(src: &str) -> LishpResult<CstNode> {
+    parse_cst_with_max_depth(src, DEFAULT_MAX_DEPTH)
+}
+
+/// The same as `parse_cst()`, except you choose how many nested lists are
+/// allowed before bailing with a `LishpError::NestingTooDeep`.
+pub fn parse_cst_with_max_depth(src: &str, max_depth: usize) -> LishpResult<CstNode> {
+    let mut lexer = Lexer::new(src);
+    let mut top_level = vec![];
+    // every list which hasn't been closed yet, its token index, and its
+    // children so far
+    let mut unclosed: Vec<(Token, usize, Vec<CstNode>)> = vec![];
+    // the index this token would have in the output of `tokenize()`
+    let mut index = 0;
+
+    while let Some(tok) = lexer.next_token()? {
+        let node = if tok.is_whitespace() || tok.is_comment() {
+            CstNode::Trivia(tok)
+        } else if &tok == "(" {
+            if unclosed.len() >= max_depth {
+                return Err(LishpError::NestingTooDeep(index));
+            }
+            unclosed.push((tok, index, vec![]));
+            index += 1;
+            continue;
+        } else if &tok == ")" {
+            match unclosed.pop() {
+                Some((open, _, children)) => {
+                    CstNode::List {
+                        open,
+                        children,
+                        close: tok,
+                    }
+                }
+                None => {
+                    let found = tok.value().to_string();
+                    return Err(LishpError::UnexpectedToken(index, found, "an expression"));
+                }
+            }
+        } else {
+            CstNode::Atom(tok)
+        };
+
+        if let CstNode::List { .. } | CstNode::Atom(_) = node {
+            index += 1;
+        }
+
+        match unclosed.last_mut() {
+            Some(&mut (_, _, ref mut children)) => children.push(node),
+            None => top_level.push(node),
+        }
+    }
+
+    if !unclosed.is_empty() {
+        let open_parens = unclosed.iter().map(|&(_, i, _)| i).collect();
+        return Err(LishpError::EOF(open_parens, "an expression or `)`"));
+    }
+
+    Ok(CstNode::Root(top_level))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::tokenize;
+    use parser::Parser;
+
+    #[test]
+    fn reconstruction_is_byte_for_byte() {
+        let inputs = vec!["(a ; c\n b)",
+                          "",
+                          "  \n",
+                          ";; just a comment",
+                          "(define (f x)\n\t(+ x 1))  ; trailing\n\n(f \"a ) string\")\n"];
+
+        for src in inputs {
+            let got = parse_cst(src).unwrap();
+            assert_eq!(got.to_source(), src);
+            assert_eq!(got.span(), Span::new(0, src.len()));
+        }
+    }
+
+    #[test]
+    fn trivia_is_kept_inside_lists() {
+        let got = parse_cst("(a ; c\n b)").unwrap();
+        let should_be = CstNode::Root(vec![CstNode::List {
+                                               open: tok!("(", 0),
+                                               children: vec![CstNode::Atom(tok!("a", 1)),
+                                                              CstNode::Trivia(tok!(" ", 2)),
+                                                              CstNode::Trivia(tok!("; c", 3)),
+                                                              CstNode::Trivia(tok!("\n ", 6)),
+                                                              CstNode::Atom(tok!("b", 8))],
+                                               close: tok!(")", 9),
+                                           }]);
+
+        assert_eq!(got, should_be);
+        assert_eq!(got.span(), Span::new(0, 10));
+    }
+
+    #[test]
+    fn error_positions_match_the_parser() {
+        let inputs = vec!["(a ; c\n (b", "a  )", "(a) ; c\n  ))"];
+
+        for src in inputs {
+            let should_be = Parser::new(tokenize(src).unwrap()).parse_program();
+            assert_eq!(parse_cst(src).map(|_| ()), should_be.map(|_| ()), "{}", src);
+        }
+
+        assert_eq!(parse_cst("(a ; c\n (b"),
+                   Err(LishpError::EOF(vec![0, 2], "an expression or `)`")));
+
+        match parse_cst("(a é)") {
+            Err(LishpError::InvalidToken(e)) => assert_eq!(e.pos(), 3),
+            other => panic!("Expected an invalid token, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_depth_limit_can_be_changed() {
+        let src = "(a (b (c)))";
+
+        assert!(parse_cst_with_max_depth(src, 3).is_ok());
+        assert_eq!(parse_cst_with_max_depth(src, 2), Err(LishpError::NestingTooDeep(4)));
+    }
+}
@@ -1,35 +1,84 @@
 //! The common error types used in `Lishp`.
 
+use std::cmp;
 use std::convert::From;
-use std::fmt::{self, Display, Formatter};
+use std::fmt::{self, Display, Formatter, Write};
 use std::num::ParseFloatError;
 
+use lexer::{InvalidTokenError, InvalidTokenKind, Span};
+
 
 /// A shortcut for any Result which contains a LishpError.
 pub type LishpResult<T> = Result<T, LishpError>;
 
 
 /// All the errors specific to Lishp.
+///
+/// Positions are token indices (i.e. the index of the token in the output of
+/// `tokenize()`), not byte offsets. Use the token's `span()` to find out
+/// where that is in the source code. The one exception is `InvalidToken`,
+/// which happens before there are any tokens and uses a byte offset.
 #[derive(Debug, PartialEq)]
 pub enum LishpError {
     /// End of file reached prematurely. The parser will tell you where it
-    /// thinks you fucked up.
-    EOF(usize),
+    /// thinks you fucked up (the token index of every paren which was never
+    /// closed, outermost first), and what it was expecting to see instead.
+    EOF(Vec<usize>, &'static str),
+
+    /// The parser came across a token it wasn't expecting. This contains the
+    /// token's index, the token itself, and what was expected instead.
+    UnexpectedToken(usize, String, &'static str),
 
     /// Converting the token to a number was unsuccessful.
     InvalidNumber(ParseFloatError),
 
     /// There aren't a balanced number of parentheses. The parser tries to
-    /// figure out which parentheses you forgot to close.
+    /// figure out which parentheses you forgot to close, and gives you its
+    /// token index.
     UnbalancedParens(usize),
+
+    /// The source code is nested more deeply than the parser allows. This
+    /// contains the token index of the parenthesis which went over the limit
+    /// (not the limit itself).
+    NestingTooDeep(usize),
+
+    /// The lexer couldn't turn the source code into tokens.
+    InvalidToken(InvalidTokenError),
+
+    /// A special form like `if` or `define` was used incorrectly. This
+    /// contains a message saying what was wrong with it and where the form
+    /// is in the source code (a synthetic span if that isn't known).
+    MalformedForm(&'static str, Span),
+
+    /// A list had the wrong number of items. This contains how many were
+    /// expected and how many there actually were (`None` if it wasn't even a
+    /// list).
+    WrongLength(usize, Option<usize>),
 }
 
 impl Display for LishpError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match *self {
-            LishpError::EOF(_) => write!(f, "Reached end of file before parsing finished"),
+            LishpError::EOF(_, expected) => write!(f, "Expected {}, found end of input", expected),
+            LishpError::UnexpectedToken(_, ref found, expected) => {
+                write!(f, "Expected {}, found `{}`", expected, found)
+            }
             LishpError::InvalidNumber(ref e) => write!(f, "InvalidNumber: {}", e),
             LishpError::UnbalancedParens(_) => write!(f, "Unbalanced parentheses"),
+            LishpError::NestingTooDeep(_) => write!(f, "Expressions are nested too deeply"),
+            LishpError::InvalidToken(e) => {
+                match e.kind() {
+                    InvalidTokenKind::Unrecognised => write!(f, "Invalid token"),
+                    InvalidTokenKind::TooLong => write!(f, "Token is too long"),
+                }
+            }
+            LishpError::MalformedForm(message, _) => write!(f, "{}", message),
+            LishpError::WrongLength(expected, Some(found)) => {
+                write!(f, "Expected a list with {} items, found {}", expected, found)
+            }
+            LishpError::WrongLength(expected, None) => {
+                write!(f, "Expected a list with {} items, found something else", expected)
+            }
         }
     }
 }
@@ -39,3 +88,81 @@ impl From<ParseFloatError> for LishpError {
         LishpError::InvalidNumber(other)
     }
 }
+
+impl From<InvalidTokenError> for LishpError {
+    fn from(other: InvalidTokenError) -> Self {
+        LishpError::InvalidToken(other)
+    }
+}
+
+
+/// Render an error message which points at a particular location in the
+/// source code, showing the file name, line number, the offending line and
+/// a caret underneath where things went wrong.
+///
+/// # Examples
+///
+/// ```
+/// let src = "(print\n  (+ 1 2)";
+/// let got = lishp::errors::render_error("foo.lisp", src, 0, "Unclosed paren");
+///
+/// assert_eq!(got, "error: Unclosed paren\n --> foo.lisp:1:1\n  |\n1 | (print\n  | ^\n");
+/// ```
+pub fn render_error(filename: &str, src: &str, pos: usize, message: &str) -> String {
+    let mut pos = cmp::min(pos, src.len());
+    while !src.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    let line_start = src[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let line_number = src[..line_start].matches('\n').count() + 1;
+    let line = src[line_start..].lines().next().unwrap_or("");
+    let before_caret = &src[line_start..pos];
+    let column = before_caret.chars().count() + 1;
+
+    // Use tabs where the original line has them so the caret lines up
+    let padding: String = before_caret.chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let gutter: String = line_number.to_string().chars().map(|_| ' ').collect();
+
+    let mut buf = String::new();
+    writeln!(buf, "error: {}", message).unwrap();
+    writeln!(buf, "{} --> {}:{}:{}", &gutter[1..], filename, line_number, column).unwrap();
+    writeln!(buf, "{} |", gutter).unwrap();
+    writeln!(buf, "{} | {}", line_number, line).unwrap();
+    writeln!(buf, "{} | {}^", gutter, padding).unwrap();
+    buf
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_an_error_on_a_later_line() {
+        let src = "(define x 1)\n\n  (print (+ x 2)\n";
+        let should_be = "error: Oops\n --> test.lisp:3:3\n  |\n3 |   (print (+ x 2)\n  |   ^\n";
+
+        let got = render_error("test.lisp", src, 16, "Oops");
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn render_an_error_with_a_wide_gutter() {
+        let src = "\n\n\n\n\n\n\n\n\n\t(foo";
+        let should_be = "error: Oops\n  --> test.lisp:10:2\n   |\n10 | \t(foo\n   | \t^\n";
+
+        let got = render_error("test.lisp", src, 10, "Oops");
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn positions_in_the_middle_of_a_character_dont_panic() {
+        let src = "(héllo)";
+        let should_be = "error: Oops\n --> test.lisp:1:3\n  |\n1 | (héllo)\n  |   ^\n";
+
+        let got = render_error("test.lisp", src, 3, "Oops");
+        assert_eq!(got, should_be);
+    }
+}
@@ -6,15 +6,28 @@
 //! get an `InvalidTokenError`.
 
 use regex::Regex;
+use std::cmp;
+use std::f64;
+use std::num::ParseFloatError;
 use std::str::FromStr;
 
+use types::Type;
+
 
 /// Turn some source code into a list of Tokens.
 ///
 /// This is pretty much just a thin wrapper around the `Lexer` struct. It
 /// creates a lexer then keeps reading tokens until there aren't any left,
-/// skipping any whitespace.
+/// skipping any whitespace and comments.
 pub fn tokenize<T: Into<String>>(src: T) -> Result<Vec<Token>, InvalidTokenError> {
+    let tokens = tokenize_all(src)?;
+    Ok(tokens.into_iter().filter(|tok| !tok.is_comment()).collect())
+}
+
+/// The same as `tokenize()`, except comments are retained. This is what you
+/// want if you are writing a tool which cares about comments (e.g. a
+/// documentation generator).
+pub fn tokenize_all<T: Into<String>>(src: T) -> Result<Vec<Token>, InvalidTokenError> {
     let mut lexer = Lexer::new(src);
     let mut tokens = vec![];
 
@@ -27,12 +40,33 @@ pub fn tokenize<T: Into<String>>(src: T) -> Result<Vec<Token>, InvalidTokenError
     Ok(tokens)
 }
 
+/// The same as `tokenize()`, except you just get each token's text and
+/// location instead of a `Token`.
+///
+/// # Examples
+///
+/// ```
+/// # use lishp::lexer::{tokenize_with_spans, Span};
+/// let got = tokenize_with_spans("(foo)").unwrap();
+///
+/// assert_eq!(got[1], ("foo".to_string(), Span::new(1, 4)));
+/// ```
+pub fn tokenize_with_spans<T: Into<String>>(src: T)
+                                            -> Result<Vec<(String, Span)>, InvalidTokenError> {
+    let tokens = tokenize(src)?;
+    Ok(tokens.into_iter().map(|tok| (tok.value, tok.span)).collect())
+}
+
 /// The location of a Token in the source code. Start and end are the idices
 /// that the token starts and ends at.
+///
+/// Tokens which don't come from the source code at all (e.g. ones inserted
+/// when desugaring) get a *synthetic* span instead.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Span {
     start: usize,
     end: usize,
+    synthetic: bool,
 }
 
 impl Span {
@@ -41,21 +75,92 @@ impl Span {
         Span {
             start: start,
             end: end,
+            synthetic: false,
+        }
+    }
+
+    /// Create a span for something which was generated instead of being
+    /// read from the source code.
+    pub fn synthetic() -> Span {
+        Span {
+            start: 0,
+            end: 0,
+            synthetic: true,
         }
     }
+
+    /// Get the smallest span which covers both `self` and `other`. Synthetic
+    /// spans don't cover anything, so merging with one gives you the other
+    /// span back.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lishp::lexer::Span;
+    /// let merged = Span::new(1, 4).merge(Span::new(7, 9));
+    /// assert_eq!(merged, Span::new(1, 9));
+    /// ```
+    pub fn merge(self, other: Span) -> Span {
+        if self.synthetic {
+            other
+        } else if other.synthetic {
+            self
+        } else {
+            Span::new(cmp::min(self.start, other.start), cmp::max(self.end, other.end))
+        }
+    }
+
+    /// Was this span generated instead of coming from the source code?
+    pub fn is_synthetic(&self) -> bool {
+        self.synthetic
+    }
+
+    /// The index the span starts at.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The index just past the end of the span.
+    pub fn end(&self) -> usize {
+        self.end
+    }
 }
 
 /// Small Error type used when an invalid token is encountered.
 #[derive(PartialEq, Debug, Copy, Clone)]
 pub struct InvalidTokenError {
     pos: usize,
+    kind: InvalidTokenKind,
+}
+
+impl InvalidTokenError {
+    /// Where in the source code the invalid token starts.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// Why the token was rejected.
+    pub fn kind(&self) -> InvalidTokenKind {
+        self.kind
+    }
+}
+
+/// The reasons a token may be invalid.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum InvalidTokenKind {
+    /// The source code didn't match any of the valid token patterns.
+    Unrecognised,
+
+    /// The token was longer than the lexer's maximum token length.
+    TooLong,
 }
 
 /// A single token and its location in the source code.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct Token {
     value: String,
     span: Span,
+    starts_line: bool,
 }
 
 impl Token {
@@ -64,9 +169,24 @@ impl Token {
         Token {
             value: value.into(),
             span: span,
+            starts_line: false,
         }
     }
 
+    /// Record whether this token is the first thing on its line (i.e. there
+    /// is only whitespace between it and the previous newline).
+    pub fn with_starts_line(mut self, starts_line: bool) -> Token {
+        self.starts_line = starts_line;
+        self
+    }
+
+    /// Is this token the first thing on its line? Only tokens which come
+    /// from a `Lexer` know this, tokens created with `Token::new()` say
+    /// `false`.
+    pub fn starts_line(&self) -> bool {
+        self.starts_line
+    }
+
     /// Attempt to parse this token into another type. Like a normal `str`,
     /// `parse()` can parse any type that implements the `FromStr` trait.
     ///
@@ -88,6 +208,11 @@ impl Token {
         self.value.as_str().trim().len() == 0
     }
 
+    /// Check whether the token is a line comment.
+    pub fn is_comment(&self) -> bool {
+        self.value.starts_with(";")
+    }
+
     /// Get the length of the token string.
     pub fn len(&self) -> usize {
         self.value.len()
@@ -107,6 +232,97 @@ impl Token {
         }
     }
 
+    /// If this is a string literal, get the text between its quotes. Escape
+    /// sequences are left untouched.
+    pub fn string_contents(&self) -> Option<&str> {
+        let value = &self.value;
+
+        if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            Some(&value[1..value.len() - 1])
+        } else {
+            None
+        }
+    }
+
+    /// If this is a pipe-quoted symbol (e.g. `|foo bar|`), get the text
+    /// between the pipes. Escape sequences (e.g. `\|`) are left untouched.
+    ///
+    /// A `|` can't appear in a plain identifier, it always starts a
+    /// pipe-quoted symbol. That means `||` is the empty symbol and `|x|` is
+    /// the symbol `x`, while something like `a|b` is an invalid token.
+    pub fn quoted_symbol_name(&self) -> Option<&str> {
+        let value = &self.value;
+
+        if value.len() >= 2 && value.starts_with('|') && value.ends_with('|') {
+            Some(&value[1..value.len() - 1])
+        } else {
+            None
+        }
+    }
+
+    /// If this is a numeric literal, get its value as either a
+    /// `Type::Integer` or `Type::Float`.
+    ///
+    /// Only literals with a decimal point or exponent are floats, so we
+    /// know which parser to use up front. Integers which are too big for an
+    /// `i64` still fall back to being floats.
+    ///
+    /// # Examples
+    /// ```
+    /// # use lishp::lexer::{Token, Span};
+    /// # use lishp::types::Type;
+    /// let tok = Token::new("1e3", Span::new(0, 3));
+    /// assert_eq!(tok.numeric_value(), Some(Type::Float(1000.0)));
+    /// ```
+    pub fn numeric_value(&self) -> Option<Type> {
+        self.try_numeric_value().and_then(Result::ok)
+    }
+
+    /// The same as `numeric_value()`, except a token which starts like a
+    /// number but isn't a valid one (e.g. `1.2.3`) gives you the error from
+    /// the float parser instead of `None`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use lishp::lexer::{Token, Span};
+    /// let tok = Token::new("1.2.3", Span::new(0, 5));
+    /// assert!(tok.try_numeric_value().unwrap().is_err());
+    ///
+    /// let tok = Token::new("foo", Span::new(0, 3));
+    /// assert!(tok.try_numeric_value().is_none());
+    /// ```
+    pub fn try_numeric_value(&self) -> Option<Result<Type, ParseFloatError>> {
+        match self.value() {
+            "+inf.0" => return Some(Ok(Type::Float(f64::INFINITY))),
+            "-inf.0" => return Some(Ok(Type::Float(f64::NEG_INFINITY))),
+            "+nan.0" | "-nan.0" => return Some(Ok(Type::Float(f64::NAN))),
+            _ => {}
+        }
+
+        let unsigned = if self.starts_with("-") || self.starts_with("+") {
+            &self.value[1..]
+        } else {
+            &self.value[..]
+        };
+
+        if !unsigned.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+
+        if !self.value.contains(&['.', 'e', 'E'][..]) {
+            if let Ok(int) = self.parse() {
+                return Some(Ok(Type::Integer(int)));
+            }
+        }
+
+        Some(self.parse().map(Type::Float))
+    }
+
+    /// Get the token's location in the source code.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
     /// Get a reference to the Token as a string.
     pub fn value(&self) -> &str {
         &self.value
@@ -114,6 +330,14 @@ impl Token {
 }
 
 
+/// Tokens are equal when they have the same text and location. Whether a
+/// token starts its line follows from its location, so it isn't compared.
+impl PartialEq for Token {
+    fn eq(&self, other: &Token) -> bool {
+        self.value == other.value && self.span == other.span
+    }
+}
+
 impl PartialEq<str> for Token {
     fn eq(&self, other: &str) -> bool {
         self.value == other.as_ref()
@@ -127,18 +351,92 @@ pub struct Lexer {
     source: String,
     position: usize,
     patterns: Vec<Regex>,
+    max_token_length: Option<usize>,
+    at_line_start: bool,
+    finished: bool,
 }
 
 impl Lexer {
-    /// Create a new lexer.
+    /// Create a new lexer which already has all of its input.
     pub fn new<T: Into<String>>(src: T) -> Lexer {
         Lexer {
             source: src.into(),
             position: 0,
             patterns: make_patterns(),
+            max_token_length: None,
+            at_line_start: true,
+            finished: true,
+        }
+    }
+
+    /// Create a lexer for input which arrives in chunks (e.g. over a
+    /// socket). Use `feed()` to give it each chunk as it arrives, then call
+    /// `finish()` once there's no more input.
+    ///
+    /// Until then, a token which could be continued by the next chunk (e.g.
+    /// `fo` when the next chunk might start with `o`) is held back until
+    /// something which definitely ends it (whitespace, a paren, etc.)
+    /// arrives, so `next_token()` says there are no more tokens *yet*. The
+    /// same goes for input which doesn't look like a token at all, seeing as
+    /// it may be the start of one (e.g. an unclosed string), so invalid
+    /// tokens are only reported after `finish()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lishp::lexer::Lexer;
+    /// let mut lexer = Lexer::streaming("(define fo");
+    /// let mut tokens = vec![];
+    ///
+    /// while let Some(tok) = lexer.next_token().unwrap() {
+    ///     tokens.push(tok.value().to_string());
+    /// }
+    /// lexer.feed("o 1)");
+    /// lexer.finish();
+    /// while let Some(tok) = lexer.next_token().unwrap() {
+    ///     tokens.push(tok.value().to_string());
+    /// }
+    ///
+    /// assert_eq!(tokens, vec!["(", "define", " ", "foo", " ", "1", ")"]);
+    /// ```
+    pub fn streaming<T: Into<String>>(src: T) -> Lexer {
+        Lexer {
+            finished: false,
+            ..Lexer::new(src)
         }
     }
 
+    /// Reject any token longer than `max_length` bytes (tokens can be any
+    /// length by default). This guards against adversarial input, such as a
+    /// massive string literal, when embedding `Lishp`.
+    pub fn with_max_token_length(mut self, max_length: usize) -> Lexer {
+        self.max_token_length = Some(max_length);
+        self
+    }
+
+    /// Append more source code to the end of the lexer's input so
+    /// tokenizing can carry on where it left off (e.g. when the source code
+    /// arrives in chunks over a socket). Spans are always relative to the
+    /// start of the concatenated input.
+    ///
+    /// If `next_token()` on a lexer created with `Lexer::new()` fails because
+    /// the input stops part way through a token (e.g. an unclosed string)
+    /// you can feed it more and try again.
+    ///
+    /// Only a lexer created with `Lexer::streaming()` holds back tokens at
+    /// the end of the input. A lexer created with `Lexer::new()` assumes it
+    /// already has everything, so a token which the new input continues may
+    /// have been returned already.
+    pub fn feed(&mut self, more: &str) {
+        self.source.push_str(more);
+    }
+
+    /// Tell a streaming lexer there's no more input coming, so any token
+    /// being held back at the end of the input can be returned.
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
     /// Get the next token in the stream.
     pub fn next_token(&mut self) -> Result<Option<Token>, InvalidTokenError> {
         if self.position >= self.source.len() {
@@ -149,27 +447,77 @@ impl Lexer {
             if let Some((start, end)) = pattern.find(&self.source[self.position..]) {
                 // Turn start/end from relative to absolute (true) indices
                 let (start, end) = (start + self.position, end + self.position);
-                let tok = Token::new(&self.source[start..end], Span::new(start, end));
+
+                if self.max_token_length.is_some_and(|max| end - start > max) {
+                    return Err(InvalidTokenError {
+                        pos: start,
+                        kind: InvalidTokenKind::TooLong,
+                    });
+                }
+
+                let tok = Token::new(&self.source[start..end], Span::new(start, end))
+                    .with_starts_line(self.at_line_start);
+
+                // wait for more input in case the next chunk continues it
+                if !self.finished && !is_complete(&tok, &self.source[end..]) {
+                    return Ok(None);
+                }
+
+                if !tok.is_whitespace() {
+                    self.at_line_start = false;
+                } else if tok.value.contains('\n') {
+                    self.at_line_start = true;
+                }
 
                 self.position = end;
                 return Ok(Some(tok));
             }
         }
 
-        Err(InvalidTokenError { pos: self.position })
+        // the rest of the input might be the start of a token which hasn't
+        // finished arriving yet (e.g. an unclosed string)
+        if !self.finished {
+            return Ok(None);
+        }
+
+        Err(InvalidTokenError {
+            pos: self.position,
+            kind: InvalidTokenKind::Unrecognised,
+        })
+    }
+}
+
+/// Is there no way more input could turn this token into a longer one,
+/// given the `rest` of the input after it?
+///
+/// Parens, strings and pipe-quoted symbols have a definite end. Whitespace
+/// and comments end as soon as something else comes after them. Anything
+/// else might carry on until the next delimiter (e.g. `1` followed by `.`
+/// might become `1.5`).
+fn is_complete(tok: &Token, rest: &str) -> bool {
+    if tok == "(" || tok == ")" || tok.string_contents().is_some() ||
+       tok.quoted_symbol_name().is_some() {
+        true
+    } else if tok.is_whitespace() || tok.is_comment() {
+        !rest.is_empty()
+    } else {
+        rest.starts_with(|c: char| c.is_whitespace() || "()\";".contains(c))
     }
 }
 
 /// Compile all the valid token patterns ahead of time.
 fn make_patterns() -> Vec<Regex> {
     let mut patterns = vec![];
-    patterns.push(Regex::new(r"^\d+(\.\d+)?").unwrap());  // floats
-    patterns.push(Regex::new(r"^-?\d+").unwrap());  // integers
+    patterns.push(Regex::new(r"^[-+]?\d+(\.\d+)?([eE][-+]?\d+)?").unwrap());  // floats
+    patterns.push(Regex::new(r"^[-+]?\d+").unwrap());  // integers
     patterns.push(Regex::new(r"^\(").unwrap());
     patterns.push(Regex::new(r"^\)").unwrap());
-    patterns.push(Regex::new(r"^[-_a-zA-Z+=*^&$!@/?%|][-_a-zA-Z0-9+=*^&$!@/?|%]*").unwrap());  // All valid identifiers
+    patterns.push(Regex::new(r"^[-+](inf|nan)\.0\b").unwrap());  // special floats
+    patterns.push(Regex::new(r"^#[tf]\b").unwrap());  // #t and #f booleans
+    patterns.push(Regex::new(r"^\|([^\\|]|\\.)*\|").unwrap());  // pipe-quoted symbols
+    patterns.push(Regex::new(r"^[-_a-zA-Z+=*^&$!@/?%][-_a-zA-Z0-9+=*^&$!@/?%]*").unwrap());  // All valid identifiers
     patterns.push(Regex::new(r#"^"([^\\"]|\\.)*""#).unwrap()); // Double quote strings
-    patterns.push(Regex::new(r"(?m)^;.*$").unwrap());  // comments
+    patterns.push(Regex::new(r"^;[^\n]*").unwrap());  // comments
     patterns.push(Regex::new(r"^\s+").unwrap());
     patterns
 }
@@ -182,7 +530,15 @@ mod tests {
 
     lexer_match!(match_numbers,
         "1" => tok!("1"),
-        "1.0" => tok!("1.0"));
+        "1.0" => tok!("1.0"),
+        "1e3" => tok!("1e3"),
+        "1.5E-2" => tok!("1.5E-2"),
+        "-1.5" => tok!("-1.5"),
+        "+5" => tok!("+5"),
+        "+5.0" => tok!("+5.0"),
+        "+inf.0" => tok!("+inf.0"),
+        "-inf.0" => tok!("-inf.0"),
+        "+nan.0" => tok!("+nan.0"));
 
     lexer_match!(match_whitespace,
         " "    => tok!(" "),
@@ -209,10 +565,178 @@ mod tests {
         "*" => tok!("*"),
         "%" => tok!("%"),
         "&" => tok!("&"),
-        "|" => tok!("|"),
         "$ARGV$" => tok!("$ARGV$")
     );
 
+    lexer_match!(match_booleans,
+        "#t" => tok!("#t"),
+        "#f" => tok!("#f"),
+        "#t)" => tok!("#t"));
+
+    #[test]
+    fn hash_prefix_needs_a_word_boundary() {
+        let mut lexer = Lexer::new("#true");
+        let got = lexer.next_token().map_err(|e| (e.pos(), e.kind()));
+        assert_eq!(got, Err((0, InvalidTokenKind::Unrecognised)));
+    }
+
+    #[test]
+    fn tokens_longer_than_the_limit_are_rejected() {
+        let src = r#"(print "a very long string")"#;
+        let mut lexer = Lexer::new(src).with_max_token_length(10);
+
+        assert_eq!(lexer.next_token(), Ok(Some(tok!("("))));
+        assert_eq!(lexer.next_token(), Ok(Some(tok!("print", 1))));
+        assert_eq!(lexer.next_token(), Ok(Some(tok!(" ", 6))));
+
+        let got = lexer.next_token().map_err(|e| (e.pos(), e.kind()));
+        assert_eq!(got, Err((7, InvalidTokenKind::TooLong)));
+    }
+
+    #[test]
+    fn tokens_can_be_any_length_by_default() {
+        let src = format!("\"{}\"", "a".repeat(100_000));
+        let mut lexer = Lexer::new(src.as_str());
+
+        assert_eq!(lexer.next_token(), Ok(Some(tok!(src.as_str()))));
+    }
+
+    #[test]
+    fn synthetic_spans() {
+        assert!(Span::synthetic().is_synthetic());
+        assert!(!Span::new(0, 0).is_synthetic());
+        assert!(Span::synthetic() != Span::new(0, 0));
+
+        // merging with a synthetic span gives you the real one back
+        let real = Span::new(3, 7);
+        assert_eq!(Span::synthetic().merge(real), real);
+        assert_eq!(real.merge(Span::synthetic()), real);
+        assert!(Span::synthetic().merge(Span::synthetic()).is_synthetic());
+    }
+
+    #[test]
+    fn get_the_contents_of_a_string_token() {
+        let inputs = vec![(tok!(r#""foo""#), Some("foo")),
+                          (tok!(r#""""#), Some("")),
+                          (tok!(r#""a\"b""#), Some(r#"a\"b"#)),
+                          (tok!("foo"), None),
+                          (tok!("\""), None)];
+
+        for (tok, should_be) in inputs {
+            assert_eq!(tok.string_contents(), should_be);
+        }
+    }
+
+    #[test]
+    fn get_the_value_of_a_numeric_token() {
+        let inputs = vec![(tok!("42"), Some(t!(Int, 42))),
+                          (tok!("4.2"), Some(t!(Float, 4.2))),
+                          (tok!("-42"), Some(t!(Int, -42))),
+                          (tok!("-4.2e1"), Some(t!(Float, -42.0))),
+                          (tok!("+42"), Some(t!(Int, 42))),
+                          (tok!("+4.2"), Some(t!(Float, 4.2))),
+                          (tok!("+-42"), None),
+                          (tok!("-"), None),
+                          (tok!("+"), None),
+                          (tok!("-foo"), None),
+                          (tok!("-inf.0"), Some(t!(Float, f64::NEG_INFINITY))),
+                          (tok!("1abc"), None),
+                          (tok!("foo"), None),
+                          (tok!(r#""42""#), None)];
+
+        for (tok, should_be) in inputs {
+            assert_eq!(tok.numeric_value(), should_be);
+        }
+    }
+
+    /// Feed a streaming lexer each chunk in turn, reading every token it's
+    /// willing to give us after each one.
+    fn tokens_from_chunks(chunks: &[&str]) -> Vec<Token> {
+        let mut lexer = Lexer::streaming("");
+        let mut got = vec![];
+
+        for chunk in chunks {
+            lexer.feed(chunk);
+            while let Some(tok) = lexer.next_token().unwrap() {
+                got.push(tok);
+            }
+        }
+
+        lexer.finish();
+        while let Some(tok) = lexer.next_token().unwrap() {
+            got.push(tok);
+        }
+
+        got
+    }
+
+    #[test]
+    fn feed_the_lexer_more_input() {
+        let inputs = vec![vec!["(+ 1", " 2)"],
+                          vec!["(define fo", "o 1)"],
+                          vec!["(+ 1", "2", "3.", "5 x)"],
+                          vec!["(print \"a", " b\") ; com", "ment\n"],
+                          vec!["(", "", "f", ")"]];
+
+        for chunks in inputs {
+            let mut lexer = Lexer::new(chunks.concat());
+            let mut should_be = vec![];
+            while let Some(tok) = lexer.next_token().unwrap() {
+                should_be.push(tok);
+            }
+
+            assert_eq!(tokens_from_chunks(&chunks), should_be, "{:?}", chunks);
+        }
+    }
+
+    #[test]
+    fn streaming_lexers_hold_back_tokens_which_might_continue() {
+        let mut lexer = Lexer::streaming("(foo 12");
+        assert_eq!(lexer.next_token(), Ok(Some(tok!("("))));
+        assert_eq!(lexer.next_token(), Ok(Some(tok!("foo", 1))));
+        assert_eq!(lexer.next_token(), Ok(Some(tok!(" ", 4))));
+        assert_eq!(lexer.next_token(), Ok(None));
+
+        lexer.feed("3)");
+        assert_eq!(lexer.next_token(), Ok(Some(tok!("123", 5))));
+        // a close paren can't be continued, so there's no need to wait
+        assert_eq!(lexer.next_token(), Ok(Some(tok!(")", 8))));
+        assert_eq!(lexer.next_token(), Ok(None));
+
+        lexer.feed(" bar");
+        assert_eq!(lexer.next_token(), Ok(Some(tok!(" ", 9))));
+        assert_eq!(lexer.next_token(), Ok(None));
+        lexer.finish();
+        assert_eq!(lexer.next_token(), Ok(Some(tok!("bar", 10))));
+        assert_eq!(lexer.next_token(), Ok(None));
+    }
+
+    #[test]
+    fn streaming_lexers_report_invalid_tokens_once_finished() {
+        let mut lexer = Lexer::streaming("(a \"unclosed");
+        assert_eq!(lexer.next_token(), Ok(Some(tok!("("))));
+        assert_eq!(lexer.next_token(), Ok(Some(tok!("a", 1))));
+        assert_eq!(lexer.next_token(), Ok(Some(tok!(" ", 2))));
+        assert_eq!(lexer.next_token(), Ok(None));
+
+        lexer.finish();
+        assert_eq!(lexer.next_token().map_err(|e| e.pos()), Err(3));
+    }
+
+    #[test]
+    fn retry_after_feeding_the_rest_of_a_token() {
+        let mut lexer = Lexer::new(r#"(print "hello"#);
+        assert_eq!(lexer.next_token(), Ok(Some(tok!("("))));
+        assert_eq!(lexer.next_token(), Ok(Some(tok!("print", 1))));
+        assert_eq!(lexer.next_token(), Ok(Some(tok!(" ", 6))));
+        assert!(lexer.next_token().is_err());
+
+        lexer.feed(r#" world")"#);
+        assert_eq!(lexer.next_token(), Ok(Some(tok!(r#""hello world""#, 7))));
+        assert_eq!(lexer.next_token(), Ok(Some(tok!(")", 20))));
+        assert_eq!(lexer.next_token(), Ok(None));
+    }
+
     #[test]
     fn empty_source() {
         let src = "";
@@ -239,6 +763,18 @@ mod tests {
                         tok!(")", 8)]
     );
 
+    token_stream!(signs_only_belong_to_numbers_when_a_digit_follows,
+        "(+ 1 +2)" => [tok!("("),
+                       tok!("+", 1),
+                       tok!(" ", 2),
+                       tok!("1", 3),
+                       tok!(" ", 4),
+                       tok!("+2", 5),
+                       tok!(")", 7)],
+
+        "(+foo)" => [tok!("("), tok!("+foo", 1), tok!(")", 5)]
+    );
+
     token_stream!(comments,
         "; aasd" => [tok!("; aasd")],
 
@@ -257,6 +793,40 @@ mod tests {
                                  tok!(")", 16)]
     );
 
+    #[test]
+    fn comments_never_skip_ahead_to_a_later_line() {
+        let src = "; one\n(f 1)\n; two\n";
+        let should_be = vec![tok!("; one", 0),
+                             tok!("(", 6),
+                             tok!("f", 7),
+                             tok!("1", 9),
+                             tok!(")", 10),
+                             tok!("; two", 12)];
+
+        let got = tokenize_all(src);
+        assert_eq!(got, Ok(should_be));
+    }
+
+    #[test]
+    fn tokens_know_whether_they_start_a_line() {
+        let got: Vec<(String, bool)> = tokenize_all("; one\n(f 1) ; two\n  ; three")
+            .unwrap()
+            .into_iter()
+            .map(|tok| (tok.value().to_string(), tok.starts_line()))
+            .collect();
+        let should_be = vec![("; one", true),
+                             ("(", true),
+                             ("f", false),
+                             ("1", false),
+                             (")", false),
+                             ("; two", false),
+                             ("; three", true)];
+        let should_be: Vec<(String, bool)> =
+            should_be.into_iter().map(|(v, s)| (v.to_string(), s)).collect();
+
+        assert_eq!(got, should_be);
+    }
+
     #[test]
     fn tokenizer() {
         let src = "(+ foo bar (9))";
@@ -272,4 +842,61 @@ mod tests {
         let got = tokenize(src);
         assert_eq!(got, Ok(should_be));
     }
+
+    #[test]
+    fn tokenize_drops_comments_but_tokenize_all_keeps_them() {
+        let src = "; comment\n(foo)";
+
+        let got = tokenize(src);
+        assert_eq!(got, Ok(vec![tok!("(", 10), tok!("foo", 11), tok!(")", 14)]));
+
+        let got = tokenize_all(src);
+        assert_eq!(got,
+                   Ok(vec![tok!("; comment", 0), tok!("(", 10), tok!("foo", 11), tok!(")", 14)]));
+    }
+
+    #[test]
+    fn spans_from_tokenize_with_spans_match_tokenize() {
+        let src = "(define x ; comment\n  \"a string\")";
+
+        let got = tokenize_with_spans(src).unwrap();
+        let should_be: Vec<(String, Span)> = tokenize(src)
+            .unwrap()
+            .into_iter()
+            .map(|tok| (tok.value().to_string(), tok.span()))
+            .collect();
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn pipe_quoted_symbols_are_a_single_token() {
+        let got = tokenize("(|weird symbol| |x|)");
+        let should_be =
+            vec![tok!("(", 0), tok!("|weird symbol|", 1), tok!("|x|", 16), tok!(")", 19)];
+        assert_eq!(got, Ok(should_be));
+
+        let got = tokenize(r"|a\|b\\| c");
+        assert_eq!(got, Ok(vec![tok!(r"|a\|b\\|", 0), tok!("c", 9)]));
+    }
+
+    #[test]
+    fn pipes_never_appear_in_plain_identifiers() {
+        let got = tokenize("(|| a)");
+        assert_eq!(got, Ok(vec![tok!("(", 0), tok!("||", 1), tok!("a", 4), tok!(")", 5)]));
+
+        assert_eq!(tokenize("|").map_err(|e| e.pos()), Err(0));
+        assert_eq!(tokenize("a|b").map_err(|e| e.pos()), Err(1));
+        assert_eq!(tokenize("|unclosed").map_err(|e| e.pos()), Err(0));
+    }
+
+    #[test]
+    fn unfinished_strings_and_unicode_are_errors() {
+        let inputs = vec![("\"", 0), ("\"abc\\\"", 0), ("(foo é)", 5), ("(a\u{1F600})", 2)];
+
+        for (src, pos) in inputs {
+            let got = tokenize(src).unwrap_err();
+            assert_eq!(got.pos(), pos, "{:?}", src);
+        }
+    }
 }
@@ -53,10 +53,14 @@ pub mod parser;
 pub mod errors;
 pub mod types;
 pub mod visitor;
+pub mod analysis;
+pub mod cst;
+pub mod optimiser;
 
 // re-export for convenience
 
+pub use cst::parse_cst;
 pub use errors::{LishpResult, LishpError};
-pub use lexer::tokenize;
+pub use lexer::{tokenize, tokenize_all, tokenize_with_spans};
 pub use parser::{parse, Parser};
 pub use types::Type;
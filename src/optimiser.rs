@@ -0,0 +1,393 @@
+//! Optimiser passes which rewrite the AST into something simpler, but
+//! equivalent.
+//!
+//! Each pass is just a `Visitor`, so running one is a matter of calling
+//! `visit()` on the tree. These passes assume the standard arithmetic and
+//! comparison functions (`+`, `=`, etc) haven't been redefined.
+//!
+//! # Examples
+//!
+//! ```
+//! # use lishp::{tokenize, parse, Type};
+//! use lishp::optimiser::ConstantFolder;
+//! use lishp::visitor::Visitor;
+//!
+//! let mut ast = parse(tokenize("(* (+ 1 2) 4)").unwrap()).unwrap();
+//! ConstantFolder.visit(&mut ast);
+//!
+//! assert_eq!(ast, Type::Integer(12));
+//! ```
+
+use std::cmp::Ordering;
+
+use types::Type;
+use visitor::Visitor;
+
+
+/// Evaluate arithmetic (`+`, `-`, `*` and `/`) and comparisons (`=`, `<`,
+/// `>`, `<=` and `>=`) ahead of time when all of their arguments are number
+/// literals.
+///
+/// Calls are left alone if anything about them isn't known up front, or if
+/// evaluating them would be an error (e.g. integer overflow or dividing an
+/// integer by zero). Integer division is only folded when it divides evenly,
+/// while float arithmetic follows the usual IEEE 754 rules, so `(/ 1.0 0)`
+/// folds to `+inf.0`. Quoted data and the names bound by `let`, `lambda` and
+/// `define` are never touched.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ConstantFolder;
+
+impl Visitor for ConstantFolder {
+    fn visit_list(&mut self, node: &mut Type) {
+        // fold from the bottom up so `(* (+ 1 2) 4)` can become `12`
+        visit_code(self, node);
+
+        if let Some(folded) = fold(node) {
+            *node = folded;
+        }
+    }
+}
+
+/// Replace an `if` whose condition is a literal boolean or `nil` with the
+/// branch which would be taken (or `nil` if there's no else-branch).
+///
+/// Conditions which are only known at runtime, like symbols or function
+/// calls, are left alone. Running a `ConstantFolder` first means things like
+/// `(if (= 1 2) a b)` will be simplified too.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DeadCodeEliminator;
+
+impl Visitor for DeadCodeEliminator {
+    fn visit_list(&mut self, node: &mut Type) {
+        visit_code(self, node);
+
+        let taken = match *node {
+            Type::List(ref mut items) => taken_branch(items),
+            _ => None,
+        };
+
+        if let Some(branch) = taken {
+            *node = branch;
+        }
+    }
+}
+
+/// If this is an `if` with a constant condition, remove and return the
+/// branch it will always take.
+fn taken_branch(items: &mut Vec<Type>) -> Option<Type> {
+    let is_if = items.first() == Some(&Type::Symbol("if".to_string()));
+    if !is_if || items.len() < 3 || items.len() > 4 {
+        return None;
+    }
+
+    match items[1] {
+        Type::Boolean(true) => Some(items.swap_remove(2)),
+        Type::Boolean(false) | Type::Nil if items.len() == 4 => items.pop(),
+        Type::Boolean(false) | Type::Nil => Some(Type::Nil),
+        _ => None,
+    }
+}
+
+/// Visit every item in a list which is code. Quoted data isn't code, and
+/// neither are the names bound by `let` or the parameter list of a `lambda`
+/// or `define`, so those never get touched.
+fn visit_code<V: Visitor>(visitor: &mut V, node: &mut Type) {
+    let items = match *node {
+        Type::List(ref mut items) => items,
+        _ => return,
+    };
+
+    let head = match items.first() {
+        Some(Type::Symbol(name)) => name.clone(),
+        _ => String::new(),
+    };
+
+    let code_starts_at = match head.as_str() {
+        "quote" => return,
+        "lambda" | "define" => 2,
+        "let" => {
+            // only the values in `((name value) ...)` are code
+            if let Some(&mut Type::List(ref mut bindings)) = items.get_mut(1) {
+                for binding in bindings.iter_mut() {
+                    if let Type::List(ref mut parts) = *binding {
+                        for value in parts.iter_mut().skip(1) {
+                            visitor.visit(value);
+                        }
+                    }
+                }
+            }
+            2
+        }
+        _ => 0,
+    };
+
+    for item in items.iter_mut().skip(code_starts_at) {
+        visitor.visit(item);
+    }
+}
+
+fn fold(node: &Type) -> Option<Type> {
+    let (head, args) = node.match_list()?;
+    if !args.iter().all(is_number) {
+        return None;
+    }
+
+    match *head {
+        Type::Symbol(ref op) => {
+            match op.as_str() {
+                "+" | "-" | "*" | "/" => arithmetic(op, args),
+                "=" | "<" | ">" | "<=" | ">=" => comparison(op, args),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_number(node: &Type) -> bool {
+    number(node).is_some()
+}
+
+/// A number literal, pulled out of its `Type` so it can be copied around.
+#[derive(Debug, Copy, Clone)]
+enum Number {
+    Integer(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn as_float(self) -> f64 {
+        match self {
+            Number::Integer(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+
+    fn into_type(self) -> Type {
+        match self {
+            Number::Integer(i) => Type::Integer(i),
+            Number::Float(f) => Type::Float(f),
+        }
+    }
+}
+
+fn number(node: &Type) -> Option<Number> {
+    match *node {
+        Type::Integer(i) => Some(Number::Integer(i)),
+        Type::Float(f) => Some(Number::Float(f)),
+        _ => None,
+    }
+}
+
+fn arithmetic(op: &str, args: &[Type]) -> Option<Type> {
+    let (first, rest) = args.split_first()?;
+    let first = number(first)?;
+
+    if rest.is_empty() {
+        // `(- x)` negates, everything else just gives its argument back
+        return match (op, first) {
+            ("-", Number::Integer(i)) => i.checked_neg().map(Type::Integer),
+            ("-", Number::Float(f)) => Some(Type::Float(-f)),
+            ("/", _) => None,
+            _ => Some(first.into_type()),
+        };
+    }
+
+    let mut result = first;
+    for arg in rest {
+        result = apply(op, result, number(arg)?)?;
+    }
+    Some(result.into_type())
+}
+
+fn apply(op: &str, left: Number, right: Number) -> Option<Number> {
+    if let (Number::Integer(a), Number::Integer(b)) = (left, right) {
+        let result = match op {
+            "+" => a.checked_add(b),
+            "-" => a.checked_sub(b),
+            "*" => a.checked_mul(b),
+            _ if a.checked_rem(b) == Some(0) => a.checked_div(b),
+            _ => None,
+        };
+        return result.map(Number::Integer);
+    }
+
+    let (a, b) = (left.as_float(), right.as_float());
+    let result = match op {
+        "+" => a + b,
+        "-" => a - b,
+        "*" => a * b,
+        _ => a / b,
+    };
+    Some(Number::Float(result))
+}
+
+fn comparison(op: &str, args: &[Type]) -> Option<Type> {
+    if args.len() < 2 {
+        return None;
+    }
+
+    let holds = args.windows(2).all(|pair| {
+        let ordering = match (number(&pair[0]), number(&pair[1])) {
+            (Some(Number::Integer(a)), Some(Number::Integer(b))) => Some(a.cmp(&b)),
+            (Some(a), Some(b)) => a.as_float().partial_cmp(&b.as_float()),
+            _ => None,
+        };
+
+        matches!((op, ordering),
+                 ("=", Some(Ordering::Equal)) |
+                 ("<", Some(Ordering::Less)) |
+                 (">", Some(Ordering::Greater)) |
+                 ("<=", Some(Ordering::Less)) |
+                 ("<=", Some(Ordering::Equal)) |
+                 (">=", Some(Ordering::Greater)) |
+                 (">=", Some(Ordering::Equal)))
+    });
+
+    Some(Type::Boolean(holds))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64;
+    use lexer::tokenize;
+    use parser::parse;
+
+    fn fold_src(src: &str) -> Type {
+        let mut ast = parse(tokenize(src).unwrap()).unwrap();
+        ConstantFolder.visit(&mut ast);
+        ast
+    }
+
+    #[test]
+    fn fold_constant_arithmetic_inside_a_larger_tree() {
+        let got = fold_src("(print (+ 1 2) (* 2 (- 10 4)))");
+        let should_be = t!(List, [t!(Sym, "print"), t!(Int, 3), t!(Int, 12)]);
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn folding_numbers() {
+        let inputs = vec![("(+ 1 2 3)", t!(Int, 6)),
+                          ("(- 5)", t!(Int, -5)),
+                          ("(+ 1 2.5)", t!(Float, 3.5)),
+                          ("(/ 10 2)", t!(Int, 5)),
+                          ("(/ 1.0 4)", t!(Float, 0.25)),
+                          ("(= 1 1.0)", t!(Bool, true)),
+                          ("(= 1 1 2)", t!(Bool, false)),
+                          ("(= (+ 1 1) 2)", t!(Bool, true))];
+
+        for (src, should_be) in inputs {
+            assert_eq!(fold_src(src), should_be, "{}", src);
+        }
+    }
+
+    fn eliminate_src(src: &str) -> Type {
+        let mut ast = parse(tokenize(src).unwrap()).unwrap();
+        DeadCodeEliminator.visit(&mut ast);
+        ast
+    }
+
+    #[test]
+    fn constant_if_conditions_pick_a_branch() {
+        let inputs = vec![("(if true (f 1) 2)", t!(List, [t!(Sym, "f"), t!(Int, 1)])),
+                          ("(if false 1 (g))", t!(List, [t!(Sym, "g")])),
+                          ("(if #f 1)", t!(Nil)),
+                          ("(if nil 1 2)", t!(Int, 2)),
+                          ("(print (if true (if false 1 2) 3))",
+                           t!(List, [t!(Sym, "print"), t!(Int, 2)]))];
+
+        for (src, should_be) in inputs {
+            assert_eq!(eliminate_src(src), should_be, "{}", src);
+        }
+    }
+
+    #[test]
+    fn dynamic_if_conditions_are_left_alone() {
+        let inputs = vec!["(if x 1 2)",
+                          "(if (f) 1 2)",
+                          "(if 0 1 2)",
+                          "(quote (if true 1 2))",
+                          "(if true 1 2 3)",
+                          "(let ((if true 1 2)) if)",
+                          "(lambda (if true 1 2) 3)"];
+
+        for src in inputs {
+            let original = parse(tokenize(src).unwrap()).unwrap();
+            assert_eq!(eliminate_src(src), original, "{}", src);
+        }
+    }
+
+    #[test]
+    fn folding_makes_more_conditions_constant() {
+        let mut ast = parse(tokenize("(if (= (+ 1 1) 3) (launch) (stay))").unwrap()).unwrap();
+
+        ConstantFolder.visit(&mut ast);
+        DeadCodeEliminator.visit(&mut ast);
+
+        assert_eq!(ast, t!(List, [t!(Sym, "stay")]));
+    }
+
+    #[test]
+    fn folding_comparisons() {
+        // the lexer can't read `<` or `>` yet, so build the calls by hand
+        let inputs = vec![("<", vec![t!(Int, 1), t!(Float, 1.5), t!(Int, 2)], true),
+                          (">", vec![t!(Int, 1), t!(Int, 2)], false),
+                          ("<=", vec![t!(Int, 1), t!(Int, 2), t!(Int, 2)], true),
+                          (">=", vec![t!(Float, 3.0), t!(Int, 3), t!(Int, 4)], false),
+                          ("=", vec![t!(Float, f64::NAN), t!(Float, f64::NAN)], false)];
+
+        for (op, args, should_be) in inputs {
+            let mut items = vec![t!(Sym, op)];
+            items.extend(args);
+            let mut ast = Type::List(items);
+
+            ConstantFolder.visit(&mut ast);
+            assert_eq!(ast, t!(Bool, should_be), "{}", op);
+        }
+    }
+
+    #[test]
+    fn anything_which_isnt_constant_is_left_alone() {
+        let inputs = vec!["(+ x 1)",
+                          "(f 1 2)",
+                          "(+ 1 \"a\")",
+                          "(quote (+ 1 2))",
+                          "(/ 1 0)",
+                          "(/ 7 2)",
+                          "(* 9223372036854775807 2)",
+                          "(/ -9223372036854775808 -1)",
+                          "(= 1)",
+                          "(let ((- 5)) -)",
+                          "(lambda (+ 1) 2)",
+                          "(define (* 2 3) 4)"];
+
+        for src in inputs {
+            let original = parse(tokenize(src).unwrap()).unwrap();
+            assert_eq!(fold_src(src), original, "{}", src);
+        }
+    }
+
+    #[test]
+    fn binding_forms_still_have_their_code_folded() {
+        let inputs = vec![("(let ((x (+ 1 2))) (* x (- 4 1)))",
+                           "(let ((x 3)) (* x 3))"),
+                          ("(lambda (x) (+ 1 2))", "(lambda (x) 3)"),
+                          ("(define (f x) (+ 1 2))", "(define (f x) 3)"),
+                          ("(define x (+ 1 2))", "(define x 3)")];
+
+        for (src, should_be) in inputs {
+            let should_be = parse(tokenize(should_be).unwrap()).unwrap();
+            assert_eq!(fold_src(src), should_be, "{}", src);
+        }
+    }
+
+    #[test]
+    fn float_division_by_zero_follows_ieee_754() {
+        assert_eq!(fold_src("(/ 1.0 0)"), t!(Float, f64::INFINITY));
+        assert_eq!(fold_src("(/ -1 0.0)"), t!(Float, f64::NEG_INFINITY));
+    }
+}
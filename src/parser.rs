@@ -3,16 +3,82 @@
 // TODO: add proper error handling for unbalanced parens
 
 use errors::{LishpError, LishpResult};
-use lexer::Token;
+use lexer::{Span, Token};
 use types::Type;
 
 
+/// The maximum nesting depth a `Parser` will accept unless told otherwise.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
 /// A convenience method for parsing a stream of Tokens into an AST.
 pub fn parse(tokens: Vec<Token>) -> LishpResult<Type> {
     let mut parser = Parser::new(tokens);
     parser.parse()
 }
 
+/// The same as `parse()`, except you also get back where the expression is in
+/// the source code.
+pub fn parse_spanned(tokens: Vec<Token>) -> LishpResult<Spanned<Type>> {
+    let mut parser = Parser::new(tokens);
+    parser.parse_spanned()
+}
+
+/// Something parsed from the source code, along with its location.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spanned<T> {
+    /// The thing itself.
+    pub node: T,
+
+    /// Where it came from. For a list this covers everything from the
+    /// opening paren to the closing one.
+    pub span: Span,
+
+    /// Where each of the node's children came from, in the same order as
+    /// `Type::children()`. This is empty for atoms.
+    pub children: Vec<SpanTree>,
+}
+
+/// The locations of an expression and everything inside it. This has the
+/// same shape as the `Type` it was parsed into, so the `n`th child of a list
+/// has its location in `children[n]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpanTree {
+    /// Where the expression is.
+    pub span: Span,
+
+    /// Where each of the expression's children are.
+    pub children: Vec<SpanTree>,
+}
+
+/// A single top-level form, along with any comments which came immediately
+/// before it.
+#[derive(Debug, PartialEq)]
+pub struct Form {
+    /// The leading comments. This will always be empty unless the parser was
+    /// told to keep comments with `Parser::with_comments()`.
+    pub comments: Vec<String>,
+
+    /// The form itself.
+    pub ast: Type,
+
+    /// Where the form is in the source code.
+    pub span: Span,
+
+    /// Where each of the form's children are in the source code, in the
+    /// same order as `Type::children()`.
+    pub children: Vec<SpanTree>,
+}
+
+impl From<Form> for Spanned<Type> {
+    fn from(form: Form) -> Spanned<Type> {
+        Spanned {
+            node: form.ast,
+            span: form.span,
+            children: form.children,
+        }
+    }
+}
+
 /// The Parser.
 ///
 /// # Examples
@@ -21,6 +87,8 @@ pub struct Parser {
     tokens: Vec<Token>,
     position: usize,
     parens_stack: Vec<usize>,
+    keep_comments: bool,
+    max_depth: usize,
 }
 
 impl Parser {
@@ -30,9 +98,30 @@ impl Parser {
             tokens: tokens,
             position: 0,
             parens_stack: vec![],
+            keep_comments: false,
+            max_depth: DEFAULT_MAX_DEPTH,
         }
     }
 
+    /// Tell the parser whether to keep the comments preceding each top-level
+    /// form in `parse_program()`. Comments are dropped by default.
+    ///
+    /// Note that `tokenize()` already strips out comments, so you'll need to
+    /// use `tokenize_all()` to get a token stream which still has them.
+    pub fn with_comments(mut self, keep_comments: bool) -> Parser {
+        self.keep_comments = keep_comments;
+        self
+    }
+
+    /// Set the maximum number of nested lists the parser will accept before
+    /// bailing with a `LishpError::NestingTooDeep` (`DEFAULT_MAX_DEPTH` by
+    /// default). The parser is recursive, so this stops malicious input from
+    /// overflowing the stack.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Parser {
+        self.max_depth = max_depth;
+        self
+    }
+
     /// Look at the next Token, but don't consume it.
     pub fn peek(&self) -> Option<&Token> {
         self.tokens.get(self.position)
@@ -49,75 +138,153 @@ impl Parser {
 
     /// Do the actual parsing and get the resultant AST.
     pub fn parse(&mut self) -> LishpResult<Type> {
+        self.parse_spanned().map(|spanned| spanned.node)
+    }
+
+    /// Parse an expression, keeping track of where it and every node inside
+    /// it were in the source code. The `Type` stays span-free, the locations
+    /// of nested nodes are kept alongside it in `children`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lishp::lexer::{tokenize, Span};
+    /// # use lishp::parser::Parser;
+    /// let tokens = tokenize("  (+ 1 2)").unwrap();
+    /// let got = Parser::new(tokens).parse_spanned().unwrap();
+    ///
+    /// assert_eq!(got.span, Span::new(2, 9));
+    /// assert_eq!(got.children[2].span, Span::new(7, 8));
+    /// ```
+    pub fn parse_spanned(&mut self) -> LishpResult<Spanned<Type>> {
         let ast = self.parse_form()?;
-        if self.position != self.tokens.len() {
-            Err(self.eof())
-        } else {
-            Ok(ast)
+        let _ = self.chomp_comments();
+
+        match self.peek() {
+            Some(tok) => Err(unexpected(self.position, tok, "end of input")),
+            None => Ok(ast),
         }
     }
 
-    fn parse_form(&mut self) -> LishpResult<Type> {
+    /// Parse a sequence of top-level forms (i.e. an entire program).
+    ///
+    /// Only comments on their own line are attached to the following form.
+    /// A comment at the end of the line a form finishes on is dropped.
+    pub fn parse_program(&mut self) -> LishpResult<Vec<Form>> {
+        let mut forms = vec![];
+
+        loop {
+            if !forms.is_empty() &&
+               self.peek().is_some_and(|tok| tok.is_comment() && !tok.starts_line()) {
+                let _ = self.next();
+            }
+
+            let comments = self.chomp_comments();
+            if self.peek().is_none() {
+                break;
+            }
+
+            let Spanned { node: ast, span, children } = self.parse_form()?;
+            forms.push(Form {
+                comments: if self.keep_comments { comments } else { vec![] },
+                ast,
+                span,
+                children,
+            });
+        }
+
+        Ok(forms)
+    }
+
+    fn parse_form(&mut self) -> LishpResult<Spanned<Type>> {
         if self.tokens.len() == 0 {
-            return Ok(Type::Nil);
+            return Ok(Spanned {
+                node: Type::Nil,
+                span: Span::synthetic(),
+                children: vec![],
+            });
+        }
+
+        // comments in the middle of a form are always thrown away
+        let _ = self.chomp_comments();
+
+        // a top-level input with nothing but comments is the same as empty
+        if self.peek().is_none() && self.parens_stack.is_empty() {
+            return Ok(Spanned {
+                node: Type::Nil,
+                span: Span::synthetic(),
+                children: vec![],
+            });
         }
 
         // try to consume a '(', if we can then we need to parse a list
-        if let Some(_) = self.chomp_open_paren() {
-            self.parse_list()
+        if let Some(open) = self.chomp_open_paren().map(Token::span) {
+            if self.parens_stack.len() > self.max_depth {
+                return Err(LishpError::NestingTooDeep(self.position - 1));
+            }
+            self.parse_list(open)
         } else {
-            self.parse_atom()
+            let span = self.peek().map_or_else(Span::synthetic, Token::span);
+            self.parse_atom().map(|node| {
+                Spanned {
+                    node,
+                    span,
+                    children: vec![],
+                }
+            })
         }
     }
 
-    fn parse_list(&mut self) -> LishpResult<Type> {
+    fn parse_list(&mut self, open: Span) -> LishpResult<Spanned<Type>> {
         let mut components: Vec<Type> = Vec::new();
+        let mut children = Vec::new();
 
         // otherwise keep parsing atoms until you hit that closing paren
-        while let None = self.chomp_close_paren() {
-            let next_atom = self.parse_form()?;
-            components.push(next_atom);
-        }
+        let close = loop {
+            let _ = self.chomp_comments();
+            if let Some(close) = self.chomp_close_paren().map(Token::span) {
+                break close;
+            }
+            if self.peek().is_none() {
+                return Err(self.eof("an expression or `)`"));
+            }
 
-        if components.len() == 0 {
-            Ok(Type::Nil)
-        } else {
-            Ok(Type::List(components))
-        }
+            let Spanned { node, span, children: grandchildren } = self.parse_form()?;
+            components.push(node);
+            children.push(SpanTree {
+                span,
+                children: grandchildren,
+            });
+        };
+
+        Ok(spanned_list(components, open.merge(close), children))
     }
 
     fn parse_atom(&mut self) -> LishpResult<Type> {
         if self.position >= self.tokens.len() {
-            return Err(self.eof());
+            return Err(self.eof("an expression"));
         }
 
+        let index = self.position;
         let next_token = self.next().unwrap();
 
-        if next_token.starts_with_number() {
-            // try parsing the token as a number
-            if let Ok(int) = next_token.parse::<i64>() {
-                Ok(Type::Integer(int))
-            } else {
-                let float: f64 = next_token.parse()?;
-                Ok(Type::Float(float))
-            }
-        } else if next_token.starts_with("\"") {
-            let mut letters: Vec<char> = next_token.value().chars().collect();
-            debug_assert!(letters.len() >= 2);
-            let _ = letters.pop();  // get rid of the trailing quote
-            let _ = letters.remove(0);
-
-            // Collect the characters back into a string and do the usual
-            // escapes (\n, \t, etc)
-            let no_quotes =
-                letters.into_iter().collect::<String>().replace(r"\n", "\n").replace(r"\t", "\t");
-
-            Ok(Type::String(no_quotes))
+        if next_token == ")" {
+            return Err(unexpected(index, next_token, "an expression"));
+        }
+
+        if let Some(number) = next_token.try_numeric_value() {
+            // numbers are only ever run through one parser, so if it looks
+            // like a number but isn't we just pass the parser's error along
+            number.map_err(LishpError::InvalidNumber)
+        } else if let Some(contents) = next_token.string_contents() {
+            Ok(Type::String(unescape(contents)))
+        } else if let Some(name) = next_token.quoted_symbol_name() {
+            Ok(Type::Symbol(unescape_symbol(name)))
         } else {
             match next_token.value() {
                 "nil" => Ok(Type::Nil),
-                "true" => Ok(Type::Boolean(true)),
-                "false" => Ok(Type::Boolean(false)),
+                "true" | "#t" => Ok(Type::Boolean(true)),
+                "false" | "#f" => Ok(Type::Boolean(false)),
                 other => Ok(Type::Symbol(other.to_string())),
             }
         }
@@ -143,15 +310,94 @@ impl Parser {
         None
     }
 
-    fn eof(&self) -> LishpError {
-        LishpError::EOF(*self.parens_stack.get(0).unwrap_or(&0))
+    fn chomp_comments(&mut self) -> Vec<String> {
+        let mut comments = vec![];
+
+        while self.peek().is_some_and(|tok| tok.is_comment()) {
+            let comment = self.next().unwrap().value().to_string();
+            comments.push(comment);
+        }
+
+        comments
+    }
+
+    fn eof(&self, expected: &'static str) -> LishpError {
+        LishpError::EOF(self.parens_stack.clone(), expected)
     }
 }
 
+/// Put a list back together once all its items have been parsed. The list
+/// covers its parens and everything inside them.
+fn spanned_list(components: Vec<Type>, parens: Span, children: Vec<SpanTree>) -> Spanned<Type> {
+    let node = if components.len() == 0 {
+        Type::Nil
+    } else {
+        Type::List(components)
+    };
+    let span = children.iter().fold(parens, |span, child| span.merge(child.span));
+
+    Spanned {
+        node,
+        span,
+        children,
+    }
+}
+
+/// Undo the escapes inside a pipe-quoted symbol. Like Common Lisp, a
+/// backslash means the next character is taken literally, so `\|` is a
+/// pipe and `\\` is a backslash.
+fn unescape_symbol(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            unescaped.extend(chars.next());
+        } else {
+            unescaped.push(c);
+        }
+    }
+
+    unescaped
+}
+
+/// Do the usual escapes (`\n`, `\t`, `\"` and `\\`). Anything else
+/// preceded by a backslash is left as-is.
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('"') => unescaped.push('"'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+fn unexpected(index: usize, tok: &Token, expected: &'static str) -> LishpError {
+    LishpError::UnexpectedToken(index, tok.value().to_string(), expected)
+}
+
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use lexer::{tokenize, tokenize_all};
     use types::Type;
 
     #[test]
@@ -173,8 +419,12 @@ mod tests {
     fn parse_valid_atoms() {
         let inputs = vec![(tok!("1"), Type::Integer(1)),
                           (tok!("1.23"), Type::Float(1.23)),
+                          (tok!("-5"), Type::Integer(-5)),
+                          (tok!("-1.25"), Type::Float(-1.25)),
                           (tok!("true"), Type::Boolean(true)),
                           (tok!("false"), Type::Boolean(false)),
+                          (tok!("#t"), Type::Boolean(true)),
+                          (tok!("#f"), Type::Boolean(false)),
                           (tok!("nil"), Type::Nil),
                           (tok!("foo"), t!(Sym, "foo")),
                           (tok!("\"foo\""), t!(String, "foo"))];
@@ -187,6 +437,49 @@ mod tests {
         }
     }
 
+    #[test]
+    fn numbers_are_classified_by_their_literal() {
+        let inputs = vec![("1e3", t!(Float, 1000.0)),
+                          ("2.5e-1", t!(Float, 0.25)),
+                          ("100", t!(Int, 100)),
+                          ("100.0", t!(Float, 100.0)),
+                          ("99999999999999999999", t!(Float, 1e20))];
+
+        for (src, should_be) in inputs {
+            let tokens = tokenize(src).unwrap();
+            assert_eq!(tokens.len(), 1);
+
+            let got = Parser::new(tokens).parse();
+            assert_eq!(got, Ok(should_be));
+        }
+    }
+
+    #[test]
+    fn hash_booleans_are_the_same_as_their_long_forms() {
+        let inputs = vec![("#t", "true"), ("#f", "false")];
+
+        for (short, long) in inputs {
+            let short = parse(tokenize(short).unwrap());
+            let long = parse(tokenize(long).unwrap());
+            assert!(short.is_ok());
+            assert_eq!(short, long);
+        }
+    }
+
+    #[test]
+    fn leading_plus_signs() {
+        let inputs = vec![("+5", t!(Int, 5)),
+                          ("+5.0", t!(Float, 5.0)),
+                          ("+", t!(Sym, "+")),
+                          ("(+ 1 2)", t!(List, [t!(Sym, "+"), t!(Int, 1), t!(Int, 2)])),
+                          ("(+ +1 -2)", t!(List, [t!(Sym, "+"), t!(Int, 1), t!(Int, -2)]))];
+
+        for (src, should_be) in inputs {
+            let got = parse(tokenize(src).unwrap());
+            assert_eq!(got, Ok(should_be));
+        }
+    }
+
     #[test]
     fn parse_basic_lists() {
         let inputs = vec![(toks!("(", "foo", ")"), t!(List, [t!(Sym, "foo")])),
@@ -238,7 +531,12 @@ mod tests {
 
     #[test]
     fn string_escapes_are_done_correctly() {
-        let inputs = vec![(r#""foo\n""#, "foo\n"), (r#""foo\t""#, "foo\t")];
+        let inputs = vec![(r#""foo\n""#, "foo\n"),
+                          (r#""foo\t""#, "foo\t"),
+                          (r#""a \"quote\"""#, "a \"quote\""),
+                          (r#""back\\slash""#, "back\\slash"),
+                          (r#""not\\n a newline""#, "not\\n a newline"),
+                          (r#""\q""#, "\\q")];
 
         for (from, to) in inputs {
             let tok = tok!(from);
@@ -252,6 +550,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn errors_say_what_was_expected() {
+        let inputs = vec![(toks!(")"),
+                           LishpError::UnexpectedToken(0, ")".to_string(), "an expression")),
+                          (toks!("(", "foo", ")", ")"),
+                           LishpError::UnexpectedToken(3, ")".to_string(), "end of input")),
+                          (toks!("(", "foo", "(", "1"),
+                           LishpError::EOF(vec![0, 2], "an expression or `)`")),
+                          (toks!("(", "(", ")", "("),
+                           LishpError::EOF(vec![0, 3], "an expression or `)`"))];
+
+        for (tokens, should_be) in inputs {
+            let mut parser = Parser::new(tokens);
+            let got = parser.parse();
+            assert_eq!(got, Err(should_be));
+        }
+    }
+
+    #[test]
+    fn input_with_only_comments_is_nil() {
+        let src = "; just a comment";
+
+        assert_eq!(parse(tokenize(src).unwrap()), Ok(t!(Nil)));
+        assert_eq!(parse(tokenize_all(src).unwrap()), Ok(t!(Nil)));
+    }
+
+    #[test]
+    fn error_messages_include_the_hint() {
+        let got = parse(toks!("(", "foo")).unwrap_err();
+        assert_eq!(got.to_string(), "Expected an expression or `)`, found end of input");
+
+        let got = parse(toks!("foo", ")")).unwrap_err();
+        assert_eq!(got.to_string(), "Expected end of input, found `)`");
+    }
+
     #[test]
     fn unbalanced_parens() {
         let inputs = vec![toks!("(", "foo"),
@@ -268,4 +601,165 @@ mod tests {
                      got);
         }
     }
+
+    fn leaf(start: usize, end: usize) -> SpanTree {
+        SpanTree {
+            span: Span::new(start, end),
+            children: vec![],
+        }
+    }
+
+    #[test]
+    fn parse_multiple_top_level_forms() {
+        let tokens = toks!("(", "foo", ")", "1", "(", "bar", ")");
+        let should_be = vec![Form {
+                                 comments: vec![],
+                                 ast: t!(List, [t!(Sym, "foo")]),
+                                 span: Span::new(0, 5),
+                                 children: vec![leaf(1, 4)],
+                             },
+                             Form {
+                                 comments: vec![],
+                                 ast: t!(Int, 1),
+                                 span: Span::new(5, 6),
+                                 children: vec![],
+                             },
+                             Form {
+                                 comments: vec![],
+                                 ast: t!(List, [t!(Sym, "bar")]),
+                                 span: Span::new(6, 11),
+                                 children: vec![leaf(7, 10)],
+                             }];
+
+        let mut parser = Parser::new(tokens);
+        let got = parser.parse_program();
+        assert_eq!(got, Ok(should_be));
+    }
+
+    #[test]
+    fn leading_comments_are_attached_to_top_level_forms() {
+        let src = ";; doc\n(define x 1) ; trailing\n;; about foo\n(foo ; ignored\n 2)";
+        let tokens = tokenize_all(src).unwrap();
+        let should_be = vec![Form {
+                                 comments: vec![";; doc".to_string()],
+                                 ast: t!(List, [t!(Sym, "define"), t!(Sym, "x"), t!(Int, 1)]),
+                                 span: Span::new(7, 19),
+                                 children: vec![leaf(8, 14), leaf(15, 16), leaf(17, 18)],
+                             },
+                             Form {
+                                 comments: vec![";; about foo".to_string()],
+                                 ast: t!(List, [t!(Sym, "foo"), t!(Int, 2)]),
+                                 span: Span::new(44, 62),
+                                 children: vec![leaf(45, 48), leaf(60, 61)],
+                             }];
+
+        let mut parser = Parser::new(tokens).with_comments(true);
+        let got = parser.parse_program();
+        assert_eq!(got, Ok(should_be));
+    }
+
+    #[test]
+    fn comments_are_dropped_by_default() {
+        let tokens = tokenize_all(";; doc\n(define x 1)").unwrap();
+        let should_be = vec![Form {
+                                 comments: vec![],
+                                 ast: t!(List, [t!(Sym, "define"), t!(Sym, "x"), t!(Int, 1)]),
+                                 span: Span::new(7, 19),
+                                 children: vec![leaf(8, 14), leaf(15, 16), leaf(17, 18)],
+                             }];
+
+        let mut parser = Parser::new(tokens);
+        let got = parser.parse_program();
+        assert_eq!(got, Ok(should_be));
+    }
+
+    #[test]
+    fn deeply_nested_input_is_an_error_not_a_stack_overflow() {
+        let tokens = vec![tok!("("); 100_000];
+
+        let mut parser = Parser::new(tokens);
+        let got = parser.parse();
+        // the error points at the 1001st `(`, which is token 1000
+        assert_eq!(got, Err(LishpError::NestingTooDeep(1000)));
+    }
+
+    #[test]
+    fn max_depth_is_configurable() {
+        let tokens = toks!("(", "(", "(", "1", ")", ")", ")");
+
+        let mut parser = Parser::new(tokens.clone()).with_max_depth(2);
+        assert_eq!(parser.parse(), Err(LishpError::NestingTooDeep(2)));
+
+        let mut parser = Parser::new(tokens).with_max_depth(3);
+        assert_eq!(parser.parse(), Ok(t!(List, [t!(List, [t!(List, [t!(Int, 1)])])])));
+    }
+
+    #[test]
+    fn spans_cover_the_whole_expression() {
+        let inputs = vec![("(foo (bar 1) 2)", Span::new(0, 15)),
+                          ("  \n(a ; comment\n b)  ", Span::new(3, 19)),
+                          ("; leading\n\"str\"", Span::new(10, 15)),
+                          ("()", Span::new(0, 2))];
+
+        for (src, span) in inputs {
+            let got = parse_spanned(tokenize_all(src).unwrap()).unwrap();
+            assert_eq!(got.span, span, "{}", src);
+            assert_eq!(got.node, parse(tokenize(src).unwrap()).unwrap());
+        }
+    }
+
+    #[test]
+    fn nested_nodes_have_spans_too() {
+        let got = parse_spanned(tokenize("(foo (bar 1) ())").unwrap()).unwrap();
+        let should_be = vec![leaf(1, 4),
+                             SpanTree {
+                                 span: Span::new(5, 12),
+                                 children: vec![leaf(6, 9), leaf(10, 11)],
+                             },
+                             leaf(13, 15)];
+
+        assert_eq!(got.span, Span::new(0, 16));
+        assert_eq!(got.children, should_be);
+        assert_eq!(got.children.len(), got.node.children().len());
+    }
+
+    #[test]
+    fn pipes_always_quote_symbols() {
+        let got = parse(tokenize("(|| |x| a)").unwrap());
+        assert_eq!(got, Ok(t!(List, [t!(Sym, ""), t!(Sym, "x"), t!(Sym, "a")])));
+    }
+
+    #[test]
+    fn empty_string_literal() {
+        let got = parse(tokenize(r#""""#).unwrap());
+        assert_eq!(got, Ok(t!(String, "")));
+    }
+
+    #[test]
+    fn malformed_numbers_are_invalid_numbers() {
+        for &src in &["1.2.3", "1e", "-12abc"] {
+            let got = parse(toks!(src));
+            let should_be = src.parse::<f64>().unwrap_err();
+            assert_eq!(got, Err(LishpError::InvalidNumber(should_be)), "{}", src);
+        }
+    }
+
+    #[test]
+    fn awkward_input_is_an_error_not_a_panic() {
+        // hand-made tokens don't have to look like anything the lexer emits
+        let inputs = vec![toks!("\""),
+                          toks!(""),
+                          toks!("(", ""),
+                          toks!("\"\\\""),
+                          toks!("-"),
+                          toks!("1e"),
+                          toks!("1.2.3"),
+                          toks!(")", "("),
+                          toks!("(", ";", ")", ")")];
+
+        for tokens in inputs {
+            let _ = Parser::new(tokens.clone()).parse();
+            let _ = Parser::new(tokens).with_comments(true).parse_program();
+        }
+    }
 }
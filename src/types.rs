@@ -3,7 +3,18 @@
 // TODO: When we start doing Objects, this will simplify down to either
 // a List or an Object. Should primitives be distinct from Objects?
 
+use std::cmp::{self, Ordering};
+use std::fmt::{self, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::slice;
+
+use errors::{LishpError, LishpResult};
+
 /// An enum containing all the possible data types that can be used in Lishp.
+///
+/// Equality is structural, except for floats which follow the usual IEEE 754
+/// rules (i.e. `NaN` is never equal to anything, not even itself).
 #[derive(Debug, PartialEq)]
 pub enum Type {
     /// A list containing other Types.
@@ -27,3 +38,787 @@ pub enum Type {
     /// Nothing...
     Nil,
 }
+
+impl Type {
+    /// Iterate over a node's immediate children. This is the elements of a
+    /// `List`, every other type of node has no children.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lishp::types::Type;
+    /// let ast = Type::List(vec![Type::Integer(1), Type::Nil]);
+    /// assert_eq!(ast.children().count(), 2);
+    /// assert_eq!(Type::Integer(1).children().count(), 0);
+    /// ```
+    pub fn children(&self) -> slice::Iter<'_, Type> {
+        match *self {
+            Type::List(ref items) => items.iter(),
+            _ => [].iter(),
+        }
+    }
+
+    /// Split a non-empty list into its first item and the rest.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lishp::types::Type;
+    /// let call = Type::List(vec![Type::Symbol("f".to_string()), Type::Integer(1)]);
+    ///
+    /// let (head, args) = call.match_list().unwrap();
+    /// assert_eq!(head, &Type::Symbol("f".to_string()));
+    /// assert_eq!(args, &[Type::Integer(1)]);
+    /// ```
+    pub fn match_list(&self) -> Option<(&Type, &[Type])> {
+        match *self {
+            Type::List(ref items) => items.split_first(),
+            _ => None,
+        }
+    }
+
+    /// Get a list's items, as long as there are exactly `n` of them. `nil`
+    /// is treated as an empty list.
+    pub fn expect_list_len(&self, n: usize) -> LishpResult<&[Type]> {
+        let items: &[Type] = match *self {
+            Type::List(ref items) => items,
+            Type::Nil => &[],
+            _ => return Err(LishpError::WrongLength(n, None)),
+        };
+
+        if items.len() == n {
+            Ok(items)
+        } else {
+            Err(LishpError::WrongLength(n, Some(items.len())))
+        }
+    }
+
+    /// Consume the tree, applying a function to every node from the bottom up
+    /// (i.e. a list's elements are transformed before the list itself). This
+    /// is handy for doing substitution passes where you want to build a new
+    /// tree instead of mutating the old one in place with a `Visitor`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lishp::types::Type;
+    /// let ast = Type::List(vec![Type::Symbol("x".to_string()), Type::Integer(1)]);
+    ///
+    /// let got = ast.deep_map(|node| match node {
+    ///     Type::Symbol(ref s) if s == "x" => Type::Integer(42),
+    ///     other => other,
+    /// });
+    ///
+    /// assert_eq!(got, Type::List(vec![Type::Integer(42), Type::Integer(1)]));
+    /// ```
+    pub fn deep_map<F>(self, mut f: F) -> Type
+        where F: FnMut(Type) -> Type
+    {
+        self.deep_map_inner(&mut f)
+    }
+
+    fn deep_map_inner<F>(self, f: &mut F) -> Type
+        where F: FnMut(Type) -> Type
+    {
+        match self {
+            Type::List(items) => {
+                let items = items.into_iter().map(|item| item.deep_map_inner(f)).collect();
+                f(Type::List(items))
+            }
+            other => f(other),
+        }
+    }
+}
+
+/// `Type` is used as the key in hash maps, so we need an `Eq` impl. Every
+/// value except `NaN` is equal to itself, so this holds for everything but
+/// `NaN`. A `NaN` key can be inserted but never found again.
+impl Eq for Type {}
+
+impl Hash for Type {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+
+        match *self {
+            Type::List(ref items) => items.hash(state),
+            Type::Integer(i) => i.hash(state),
+            Type::Float(f) => {
+                // 0.0 and -0.0 compare equal so they need to hash the same,
+                // everything else can just use its bit pattern
+                let f = if f == 0.0 { 0.0 } else { f };
+                f.to_bits().hash(state);
+            }
+            Type::String(ref s) |
+            Type::Symbol(ref s) => s.hash(state),
+            Type::Boolean(b) => b.hash(state),
+            Type::Nil => {}
+        }
+    }
+}
+
+/// Types also have a total order, so a list of mixed values can always be
+/// sorted the same way and a `Type` can be used as a `BTreeMap` key.
+///
+/// Values of different types are ordered by type, going `nil`, booleans,
+/// integers, floats, strings, symbols then lists. Values of the same type
+/// are compared the way you'd expect (`false` before `true`, lists
+/// element-wise), except floats which use `f64::total_cmp()`. That means
+/// `NaN` sorts after positive infinity and `-0.0` sorts before `0.0`.
+///
+/// This is the one place where `Ord` and `==` disagree. `cmp()` says a `NaN`
+/// is `Equal` to itself and `-0.0` is `Less` than `0.0`, whereas `==` says
+/// `NaN != NaN` and `-0.0 == 0.0`. That means a `NaN` key can be found in a
+/// `BTreeMap` but not in a `HashMap`.
+impl Ord for Type {
+    fn cmp(&self, other: &Type) -> Ordering {
+        match (self, other) {
+            (Type::Boolean(a), Type::Boolean(b)) => a.cmp(b),
+            (Type::Integer(a), Type::Integer(b)) => a.cmp(b),
+            (Type::Float(a), Type::Float(b)) => a.total_cmp(b),
+            (Type::String(a), Type::String(b)) |
+            (Type::Symbol(a), Type::Symbol(b)) => a.cmp(b),
+            (Type::List(a), Type::List(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for Type {
+    fn partial_cmp(&self, other: &Type) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Type {
+    /// Where this type of value goes when sorting values of different types.
+    fn rank(&self) -> u8 {
+        match *self {
+            Type::Nil => 0,
+            Type::Boolean(_) => 1,
+            Type::Integer(_) => 2,
+            Type::Float(_) => 3,
+            Type::String(_) => 4,
+            Type::Symbol(_) => 5,
+            Type::List(_) => 6,
+        }
+    }
+}
+
+impl From<i64> for Type {
+    fn from(other: i64) -> Self {
+        Type::Integer(other)
+    }
+}
+
+impl From<f64> for Type {
+    fn from(other: f64) -> Self {
+        Type::Float(other)
+    }
+}
+
+impl From<bool> for Type {
+    fn from(other: bool) -> Self {
+        Type::Boolean(other)
+    }
+}
+
+/// Strings become `Type::String`, if you want a symbol you need to say so
+/// explicitly with `Type::Symbol`.
+impl From<String> for Type {
+    fn from(other: String) -> Self {
+        Type::String(other)
+    }
+}
+
+impl<'a> From<&'a str> for Type {
+    fn from(other: &'a str) -> Self {
+        Type::String(other.to_string())
+    }
+}
+
+impl From<Vec<Type>> for Type {
+    fn from(other: Vec<Type>) -> Self {
+        Type::List(other)
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.fmt_with_precision(f, None)
+    }
+}
+
+impl Type {
+    /// Display this value with every float rounded to `precision`
+    /// significant digits (e.g. so `0.30000000000000004` shows up as `0.3`).
+    /// Only the way the value is printed changes, the value itself keeps its
+    /// full precision.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lishp::types::Type;
+    /// let third = Type::Float(1.0 / 3.0);
+    ///
+    /// assert_eq!(third.to_string(), "0.3333333333333333");
+    /// assert_eq!(third.display_with(3).to_string(), "0.333");
+    /// ```
+    pub fn display_with(&self, precision: usize) -> DisplayWith<'_> {
+        DisplayWith {
+            value: self,
+            precision,
+        }
+    }
+
+    /// Serialize this value as source code which, when fed back through
+    /// `tokenize()` and `parse()`, gives you an equal `Type`.
+    ///
+    /// Unlike `Display`, strings are always quoted and escaped so they can't
+    /// be mistaken for symbols. There are a couple caveats, though:
+    ///
+    /// - An empty list is written as `()`, which the parser reads back as
+    ///   `nil`
+    /// - Symbols which wouldn't be read back as the same symbol (e.g. `true`
+    ///   or `foo bar`) are wrapped in pipes, like `|foo bar|`, with any `|`
+    ///   or `\` inside them escaped by a backslash
+    /// - `NaN` is written as `+nan.0`, which parses back to `NaN`, but `NaN`
+    ///   is never equal to itself
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use lishp::types::Type;
+    /// let value = Type::List(vec![Type::Symbol("print".to_string()),
+    ///                             Type::String("hello \"world\"".to_string())]);
+    ///
+    /// assert_eq!(value.to_sexpr_string(), r#"(print "hello \"world\"")"#);
+    /// assert_eq!(value.to_string(), r#"(print hello "world")"#);
+    /// ```
+    pub fn to_sexpr_string(&self) -> String {
+        match *self {
+            Type::List(ref items) => {
+                let items: Vec<String> = items.iter().map(Type::to_sexpr_string).collect();
+                format!("({})", items.join(" "))
+            }
+            Type::String(ref s) => escape_string(s),
+            Type::Symbol(ref s) => escape_symbol(s),
+            ref other => other.to_string(),
+        }
+    }
+
+    fn fmt_with_precision(&self, f: &mut Formatter, precision: Option<usize>) -> fmt::Result {
+        match *self {
+            Type::List(ref items) => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    item.fmt_with_precision(f, precision)?;
+                }
+                write!(f, ")")
+            }
+            Type::Integer(i) => write!(f, "{}", i),
+            Type::Float(n) if n.is_nan() => write!(f, "+nan.0"),
+            Type::Float(n) if n.is_infinite() => {
+                if n > 0.0 {
+                    write!(f, "+inf.0")
+                } else {
+                    write!(f, "-inf.0")
+                }
+            }
+            Type::Float(n) => {
+                match precision {
+                    Some(precision) => write!(f, "{}", round_to_significant_digits(n, precision)),
+                    // Debug makes sure there's always a decimal point (or exponent)
+                    None => write!(f, "{:?}", n),
+                }
+            }
+            Type::String(ref s) |
+            Type::Symbol(ref s) => write!(f, "{}", s),
+            Type::Boolean(b) => write!(f, "{}", b),
+            Type::Nil => write!(f, "nil"),
+        }
+    }
+}
+
+/// Displays a `Type` with its floats rounded to a certain number of
+/// significant digits. This is created by `Type::display_with()`.
+#[derive(Debug, Copy, Clone)]
+pub struct DisplayWith<'a> {
+    value: &'a Type,
+    precision: usize,
+}
+
+impl<'a> Display for DisplayWith<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.value.fmt_with_precision(f, Some(self.precision))
+    }
+}
+
+/// Wrap a string in double quotes, escaping anything the parser would
+/// otherwise choke on.
+fn escape_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str(r#"\""#),
+            '\\' => escaped.push_str(r"\\"),
+            '\n' => escaped.push_str(r"\n"),
+            '\t' => escaped.push_str(r"\t"),
+            other => escaped.push(other),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+/// Wrap a symbol in pipes if the lexer would read it as something else (or
+/// as more than one token), escaping any pipes or backslashes inside it.
+fn escape_symbol(s: &str) -> String {
+    if is_plain_symbol(s) {
+        return s.to_string();
+    }
+
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('|');
+    for c in s.chars() {
+        if c == '|' || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped.push('|');
+
+    escaped
+}
+
+fn is_plain_symbol(s: &str) -> bool {
+    let is_initial = |c: char| c.is_ascii_alphabetic() || "-_+=*^&$!@/?%".contains(c);
+    let has_valid_chars = s.chars().next().is_some_and(is_initial) &&
+                          s.chars().all(|c| is_initial(c) || c.is_ascii_digit());
+    let looks_like_a_number = (s.starts_with('-') || s.starts_with('+')) &&
+                              s[1..].starts_with(|c: char| c.is_ascii_digit());
+
+    has_valid_chars && !looks_like_a_number && !["nil", "true", "false"].contains(&s)
+}
+
+/// Format a finite float with (at most) `precision` significant digits,
+/// dropping any trailing zeroes after the decimal point.
+fn round_to_significant_digits(n: f64, precision: usize) -> String {
+    let precision = cmp::max(precision, 1) as i32;
+    if n == 0.0 {
+        return String::from("0.0");
+    }
+
+    let exponent = n.abs().log10().floor() as i32;
+
+    // really big or really small numbers get scientific notation
+    if exponent.abs() >= 16 {
+        return format!("{:.*e}", precision as usize - 1, n);
+    }
+
+    let decimals = precision - 1 - exponent;
+    let mut formatted = if decimals > 0 {
+        format!("{:.*}", decimals as usize, n)
+    } else {
+        // we need to round off digits before the decimal point
+        let factor = 10_f64.powi(-decimals);
+        format!("{:.1}", (n / factor).round() * factor)
+    };
+
+    while formatted.ends_with('0') && !formatted.ends_with(".0") {
+        let _ = formatted.pop();
+    }
+    formatted
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::{BTreeMap, HashMap};
+    use std::f64;
+    use lexer::tokenize;
+    use parser::parse;
+
+    fn hash_of(t: &Type) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn count_every_node_using_children() {
+        let ast = t!(List,
+                     [t!(Sym, "+"),
+                      t!(List, [t!(Sym, "*"), t!(Int, 2), t!(Int, 3)]),
+                      t!(List, []),
+                      t!(Int, 4)]);
+
+        let mut stack = vec![&ast];
+        let mut count = 0;
+
+        while let Some(node) = stack.pop() {
+            count += 1;
+            stack.extend(node.children());
+        }
+
+        assert_eq!(count, 8);
+    }
+
+    #[test]
+    fn use_types_as_hashmap_keys() {
+        let mut map = HashMap::new();
+        let _ = map.insert(t!(Int, 5), "int");
+        let _ = map.insert(t!(String, "foo"), "string");
+        let _ = map.insert(t!(Sym, "foo"), "symbol");
+        let _ = map.insert(t!(List, [t!(Int, 1), t!(Bool, true)]), "list");
+
+        assert_eq!(map.get(&t!(Int, 5)), Some(&"int"));
+        assert_eq!(map.get(&t!(String, "foo")), Some(&"string"));
+        assert_eq!(map.get(&t!(Sym, "foo")), Some(&"symbol"));
+        assert_eq!(map.get(&t!(List, [t!(Int, 1), t!(Bool, true)])), Some(&"list"));
+        assert_eq!(map.get(&t!(Int, 6)), None);
+    }
+
+    #[test]
+    fn equal_types_hash_the_same() {
+        let inputs = vec![(t!(Float, 0.0), t!(Float, -0.0)),
+                          (t!(Float, 1.5), t!(Float, 1.5)),
+                          (t!(Nil), t!(Nil)),
+                          (t!(List, [t!(Sym, "a")]), t!(List, [t!(Sym, "a")]))];
+
+        for (left, right) in inputs {
+            assert_eq!(left, right);
+            assert_eq!(hash_of(&left), hash_of(&right));
+        }
+    }
+
+    #[test]
+    fn deep_map_doubles_every_integer() {
+        let ast = t!(List,
+                     [t!(Int, 1),
+                      t!(Sym, "foo"),
+                      t!(List, [t!(Int, 2), t!(List, [t!(Int, 3)])]),
+                      t!(Float, 1.5)]);
+        let should_be = t!(List,
+                           [t!(Int, 2),
+                            t!(Sym, "foo"),
+                            t!(List, [t!(Int, 4), t!(List, [t!(Int, 6)])]),
+                            t!(Float, 1.5)]);
+
+        let got = ast.deep_map(|node| match node {
+            Type::Integer(i) => Type::Integer(i * 2),
+            other => other,
+        });
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn deep_map_visits_children_before_parents() {
+        let ast = t!(List, [t!(Int, 1), t!(List, [t!(Int, 2)])]);
+        let mut seen = vec![];
+
+        let _ = ast.deep_map(|node| {
+            seen.push(match node {
+                Type::List(ref items) => format!("list of {}", items.len()),
+                ref other => format!("{:?}", other),
+            });
+            node
+        });
+
+        assert_eq!(seen,
+                   vec!["Integer(1)", "Integer(2)", "list of 1", "list of 2"]);
+    }
+
+    #[test]
+    fn display_types() {
+        let inputs = vec![(t!(Nil), "nil"),
+                          (t!(Bool, true), "true"),
+                          (t!(Int, -5), "-5"),
+                          (t!(Float, 1.0), "1.0"),
+                          (t!(Float, 0.25), "0.25"),
+                          (t!(String, "foo bar"), "foo bar"),
+                          (t!(Sym, "foo"), "foo"),
+                          (t!(List, [t!(Sym, "+"), t!(Int, 1), t!(List, [t!(Float, 2.5)])]),
+                           "(+ 1 (2.5))")];
+
+        for (input, should_be) in inputs {
+            assert_eq!(input.to_string(), should_be);
+        }
+    }
+
+    #[test]
+    fn special_floats_round_trip_through_parse_and_display() {
+        let inputs = vec![("+inf.0", f64::INFINITY), ("-inf.0", f64::NEG_INFINITY)];
+
+        for (src, should_be) in inputs {
+            let got = parse(tokenize(src).unwrap()).unwrap();
+            assert_eq!(got, t!(Float, should_be));
+            assert_eq!(got.to_string(), src);
+        }
+
+        let got = parse(tokenize("+nan.0").unwrap()).unwrap();
+        match got {
+            Type::Float(n) => assert!(n.is_nan()),
+            other => panic!("Expected NaN, got {:?}", other),
+        }
+        assert_eq!(got.to_string(), "+nan.0");
+    }
+
+    #[test]
+    fn nan_is_not_equal_to_itself() {
+        assert!(t!(Float, f64::NAN) != t!(Float, f64::NAN));
+    }
+
+    #[test]
+    fn nan_keys_are_never_found_again() {
+        let mut map = HashMap::new();
+        let _ = map.insert(t!(Float, f64::NAN), "nan");
+        let _ = map.insert(t!(Float, 0.0), "zero");
+
+        assert_eq!(map.get(&t!(Float, f64::NAN)), None);
+        assert_eq!(map.get(&t!(Float, -0.0)), Some(&"zero"));
+    }
+
+    #[test]
+    fn display_floats_with_limited_precision() {
+        let inputs = vec![(0.1 + 0.2, 3, "0.3"),
+                          (2.0 / 3.0, 4, "0.6667"),
+                          (1.5, 10, "1.5"),
+                          (9.99, 2, "10.0"),
+                          (123456.0, 2, "120000.0"),
+                          (-0.000123456, 3, "-0.000123"),
+                          (0.0, 5, "0.0"),
+                          (1.23456e20, 3, "1.23e20")];
+
+        for (n, precision, should_be) in inputs {
+            let got = t!(Float, n).display_with(precision).to_string();
+            assert_eq!(got, should_be);
+        }
+    }
+
+    #[test]
+    fn display_with_only_changes_floats() {
+        let value = t!(List, [t!(Float, 0.1 + 0.2), t!(Int, 123456), t!(Sym, "x")]);
+
+        assert_eq!(value.to_string(), "(0.30000000000000004 123456 x)");
+        assert_eq!(value.display_with(2).to_string(), "(0.3 123456 x)");
+    }
+
+    /// A tiny xorshift random number generator so the round-trip test can
+    /// throw lots of different trees at the parser while staying
+    /// deterministic.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+
+        fn pick(&mut self, options: &str) -> char {
+            let chars: Vec<char> = options.chars().collect();
+            chars[self.below(chars.len() as u64) as usize]
+        }
+    }
+
+    fn random_type(rng: &mut Rng, depth: usize) -> Type {
+        let variants = if depth == 0 { 6 } else { 7 };
+
+        match rng.below(variants) {
+            0 => t!(Int, rng.next() as i64),
+            1 => {
+                let numerator = (rng.next() as i64 >> rng.below(60)) as f64;
+                let denominator = (rng.below(1000) + 1) as f64;
+                t!(Float, numerator / denominator)
+            }
+            2 => {
+                let len = rng.below(8);
+                let s: String = (0..len).map(|_| rng.pick("ab \"\\\n\t;()é")).collect();
+                t!(String, s)
+            }
+            3 => {
+                let mut name = rng.pick("abcxyz_*!?/=%| \\(").to_string();
+                for _ in 0..rng.below(5) {
+                    name.push(rng.pick("abcxyz_*!?/=%| \\(-+0123456789"));
+                }
+                t!(Sym, name)
+            }
+            4 => t!(Bool, rng.below(2) == 0),
+            5 => t!(Nil),
+            _ => {
+                let len = rng.below(4);
+                Type::List((0..len).map(|_| random_type(rng, depth - 1)).collect())
+            }
+        }
+    }
+
+    #[test]
+    fn serialized_types_parse_back_to_the_original() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+
+        for _ in 0..250 {
+            let original = random_type(&mut rng, 3);
+            let src = original.to_sexpr_string();
+
+            // empty lists are written as `()`, which reads back as nil
+            let should_be = original.deep_map(|node| match node {
+                Type::List(ref items) if items.is_empty() => Type::Nil,
+                other => other,
+            });
+
+            let got = parse(tokenize(src.as_str()).unwrap()).unwrap();
+            assert_eq!(got, should_be, "{}", src);
+        }
+    }
+
+    #[test]
+    fn empty_lists_read_back_as_nil() {
+        let src = t!(List, []).to_sexpr_string();
+        assert_eq!(src, "()");
+
+        let got = parse(tokenize(src.as_str()).unwrap()).unwrap();
+        assert_eq!(got, t!(Nil));
+    }
+
+    #[test]
+    fn serialize_special_values() {
+        let inputs = vec![(t!(String, ""), r#""""#),
+                          (t!(String, "a\nb"), r#""a\nb""#),
+                          (t!(Float, 1.0), "1.0"),
+                          (t!(Float, f64::NEG_INFINITY), "-inf.0"),
+                          (t!(List, []), "()")];
+
+        for (value, should_be) in inputs {
+            assert_eq!(value.to_sexpr_string(), should_be);
+        }
+    }
+
+    #[test]
+    fn splitting_lists_into_head_and_tail() {
+        let list = t!(List, [t!(Sym, "f"), t!(Int, 1), t!(Int, 2)]);
+        assert_eq!(list.match_list(), Some((&t!(Sym, "f"), &[t!(Int, 1), t!(Int, 2)][..])));
+
+        let single = t!(List, [t!(Sym, "f")]);
+        assert_eq!(single.match_list(), Some((&t!(Sym, "f"), &[][..])));
+
+        assert_eq!(t!(List, []).match_list(), None);
+        assert_eq!(t!(Nil).match_list(), None);
+        assert_eq!(t!(Int, 5).match_list(), None);
+    }
+
+    #[test]
+    fn checking_a_lists_length() {
+        let list = t!(List, [t!(Int, 1), t!(Int, 2)]);
+
+        assert_eq!(list.expect_list_len(2), Ok(&[t!(Int, 1), t!(Int, 2)][..]));
+        assert_eq!(list.expect_list_len(3), Err(LishpError::WrongLength(3, Some(2))));
+        assert_eq!(t!(Nil).expect_list_len(0), Ok(&[][..]));
+        assert_eq!(t!(Sym, "x").expect_list_len(1), Err(LishpError::WrongLength(1, None)));
+    }
+
+    #[test]
+    fn types_from_rust_values() {
+        assert_eq!(Type::from(5), t!(Int, 5));
+        assert_eq!(Type::from(1.5), t!(Float, 1.5));
+        assert_eq!(Type::from(true), t!(Bool, true));
+        assert_eq!(Type::from("foo"), t!(String, "foo"));
+        assert_eq!(Type::from("foo".to_string()), t!(String, "foo"));
+        assert_eq!(Type::from(vec![Type::from(1), Type::from("x")]),
+                   t!(List, [t!(Int, 1), t!(String, "x")]));
+    }
+
+    #[test]
+    fn sorting_a_mixed_list() {
+        let mut items = vec![t!(Int, 3), t!(String, "a"), t!(Int, 1), t!(Bool, true)];
+        items.sort();
+
+        assert_eq!(items, vec![t!(Bool, true), t!(Int, 1), t!(Int, 3), t!(String, "a")]);
+    }
+
+    #[test]
+    fn every_type_has_its_place() {
+        let ordered = vec![t!(Nil),
+                           t!(Bool, false),
+                           t!(Bool, true),
+                           t!(Int, -5),
+                           t!(Int, 100),
+                           t!(Float, f64::NEG_INFINITY),
+                           t!(Float, -0.0),
+                           t!(Float, 0.0),
+                           t!(Float, 1.5),
+                           t!(Float, f64::INFINITY),
+                           t!(Float, f64::NAN),
+                           t!(String, "a"),
+                           t!(String, "b"),
+                           t!(Sym, "a"),
+                           t!(List, [t!(Int, 1)]),
+                           t!(List, [t!(Int, 1), t!(Int, 2)]),
+                           t!(List, [t!(Int, 2)])];
+
+        for window in ordered.windows(2) {
+            assert_eq!(window[0].cmp(&window[1]), Ordering::Less, "{:?}", window);
+        }
+    }
+
+    #[test]
+    fn types_can_be_btreemap_keys() {
+        let mut map = BTreeMap::new();
+        let _ = map.insert(t!(Sym, "x"), 1);
+        let _ = map.insert(t!(Int, 7), 2);
+        let _ = map.insert(t!(Float, f64::NAN), 3);
+
+        assert_eq!(map.get(&t!(Sym, "x")), Some(&1));
+        assert_eq!(map.get(&t!(Float, f64::NAN)), Some(&3));
+        assert_eq!(map.keys().next(), Some(&t!(Int, 7)));
+    }
+
+    #[test]
+    fn ordering_and_equality_disagree_on_nan_and_negative_zero() {
+        let nan = t!(Float, f64::NAN);
+        assert!(nan != nan);
+        assert_eq!(nan.cmp(&nan), Ordering::Equal);
+
+        let (neg_zero, zero) = (t!(Float, -0.0), t!(Float, 0.0));
+        assert_eq!(neg_zero, zero);
+        assert_eq!(neg_zero.cmp(&zero), Ordering::Less);
+    }
+
+    #[test]
+    fn weird_symbols_are_pipe_quoted() {
+        let inputs = vec![("foo", "foo"),
+                          ("+", "+"),
+                          ("weird symbol", "|weird symbol|"),
+                          ("true", "|true|"),
+                          ("nil", "|nil|"),
+                          ("-5", "|-5|"),
+                          ("(x)", "|(x)|"),
+                          ("|x|", r"|\|x\||"),
+                          ("a b|", r"|a b\||"),
+                          ("a|b", r"|a\|b|"),
+                          (r"back\slash", r"|back\\slash|"),
+                          ("", "||")];
+
+        for (name, should_be) in inputs {
+            let original = t!(Sym, name);
+            let src = original.to_sexpr_string();
+            assert_eq!(src, should_be);
+
+            let got = parse(tokenize(src.as_str()).unwrap()).unwrap();
+            assert_eq!(got, original, "{}", src);
+        }
+    }
+}
@@ -41,20 +41,19 @@ pub trait Visitor {
         }
     }
 
-    /// Just recursively visit each node in the list.
+    /// Just recursively visit each node in the list. Passing in anything
+    /// other than a list is a no-op.
     fn visit_list(&mut self, node: &mut Type) {
-        match *node {
-            Type::List(ref mut list) => {
-                for node in list.iter_mut() {
-                    self.visit(node);
-                }
+        if let Type::List(ref mut list) = *node {
+            for node in list.iter_mut() {
+                self.visit(node);
             }
-            _ => unreachable!("Should never get anything other than a List in visit_list()"),
         }
     }
 
     /// Visiting an atom simply delegates to the appropriate visitor for that
-    /// node type (`visit_boolean()`, `visit_integer()`, etc).
+    /// node type (`visit_boolean()`, `visit_integer()`, etc). Lists aren't
+    /// atoms, so they get ignored.
     fn visit_atom(&mut self, node: &mut Type) {
         match *node {
             Type::Boolean(_) => self.visit_boolean(node),
@@ -62,10 +61,9 @@ pub trait Visitor {
             Type::Float(_) => self.visit_float(node),
             Type::String(_) => self.visit_string(node),
             Type::Symbol(_) => self.visit_symbol(node),
-            Type::Nil => {
+            Type::Nil | Type::List(_) => {
                 // this should be a no-op
             }
-            _ => unreachable!("Shouldn't have any Lists here"),
         }
     }
 
@@ -147,4 +145,13 @@ mod tests {
         assert_eq!(visitor.visit_count, 5);
     }
 
+    #[test]
+    fn visiting_the_wrong_kind_of_node_does_nothing() {
+        let mut visitor = DummyVisitor { visit_count: 0 };
+
+        visitor.visit_list(&mut t!(Int, 5));
+        visitor.visit_atom(&mut t!(List, [t!(Int, 5)]));
+
+        assert_eq!(visitor.visit_count, 0);
+    }
 }
@@ -0,0 +1,97 @@
+extern crate tempfile;
+
+use std::io::Write;
+use std::process::Command;
+
+use tempfile::NamedTempFile;
+
+const INTERPRETER: &str = env!("CARGO_BIN_EXE_interpreter");
+
+/// Run the interpreter on some source code, returning the exit status and
+/// whatever it wrote to stderr.
+fn run(src: &str) -> (bool, String, String) {
+    let mut f = NamedTempFile::new().unwrap();
+    write!(f, "{}", src).unwrap();
+
+    let filename = f.path().display().to_string();
+    let output = Command::new(INTERPRETER).arg(&filename).output().unwrap();
+
+    (output.status.success(), filename, String::from_utf8(output.stderr).unwrap())
+}
+
+#[test]
+fn unbalanced_parens_point_at_the_offending_line() {
+    let (success, filename, stderr) = run("(define x 1)\n\n  (print (+ x 2)\n");
+
+    assert!(!success);
+    let should_be = format!("error: Parsing Error: Expected an expression or `)`, found end of input
+ --> {}:3:3
+  |
+3 |   (print (+ x 2)
+  |   ^
+",
+                            filename);
+    assert_eq!(stderr, should_be);
+}
+
+#[test]
+fn invalid_tokens_point_at_the_offending_line() {
+    let (success, filename, stderr) = run("(print\n  1 . 2)");
+
+    assert!(!success);
+    assert!(stderr.contains(&format!("{}:2:5", filename)), "{}", stderr);
+    assert!(stderr.contains("2 |   1 . 2)\n  |     ^\n"), "{}", stderr);
+}
+
+#[test]
+fn unexpected_close_parens_are_pointed_at() {
+    let (success, filename, stderr) = run("(print 1))\n");
+
+    assert!(!success);
+    let should_be = format!("error: Parsing Error: Expected an expression, found `)`
+ --> {}:1:10
+  |
+1 | (print 1))
+  |          ^
+",
+                            filename);
+    assert_eq!(stderr, should_be);
+}
+
+#[test]
+fn malformed_special_forms_are_syntax_errors() {
+    let (success, filename, stderr) = run("(define x 1)\n(if x)\n");
+
+    assert!(!success);
+    let should_be = format!("error: Syntax Error: `if` requires a condition and a then-branch
+ --> {}:2:1
+  |
+2 | (if x)
+  | ^
+",
+                            filename);
+    assert_eq!(stderr, should_be);
+}
+
+#[test]
+fn nested_malformed_forms_are_pointed_at() {
+    let (success, filename, stderr) = run("(define (f x)\n  (print\n    (if x)))\n");
+
+    assert!(!success);
+    let should_be = format!("error: Syntax Error: `if` requires a condition and a then-branch
+ --> {}:3:5
+  |
+3 |     (if x)))
+  |     ^
+",
+                            filename);
+    assert_eq!(stderr, should_be);
+}
+
+#[test]
+fn valid_programs_succeed() {
+    let (success, _, stderr) = run("; a comment\n(define x 1)\n(print x)\n");
+
+    assert!(success, "{}", stderr);
+    assert_eq!(stderr, "");
+}